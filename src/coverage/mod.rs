@@ -0,0 +1,308 @@
+use std::{cell::RefCell, collections::BTreeMap, fmt::Write};
+
+use crate::{
+    interpreter::{Interpreter, RuntimeError, Tracer},
+    lex::Token,
+    parse::Statement,
+};
+
+/// Counts how often each statement line, function and branch of a
+/// script runs, for an lcov report and a terminal summary.
+pub struct Coverage {
+    path: String,
+    state: RefCell<State>,
+}
+
+struct State {
+    /// Hits per line that holds a statement.
+    lines: BTreeMap<usize, u64>,
+    /// Calls per function, keyed by declaration line and name.
+    functions: BTreeMap<(usize, String), u64>,
+    /// Keyed by the line and byte offset of the `if`, `while` or `for`.
+    branches: BTreeMap<(usize, usize), Branch>,
+}
+
+struct Branch {
+    keyword: String,
+    /// How often the body (or `then` branch) ran and how often it was
+    /// skipped.
+    taken: [u64; 2],
+}
+
+impl Coverage {
+    pub fn new(path: &str, stmts: &[Statement]) -> Self {
+        let mut state = State {
+            lines: BTreeMap::new(),
+            functions: BTreeMap::new(),
+            branches: BTreeMap::new(),
+        };
+        state.register(stmts);
+
+        Coverage {
+            path: path.to_string(),
+            state: RefCell::new(state),
+        }
+    }
+
+    /// The counts in lcov's tracefile format.
+    pub fn lcov(&self) -> String {
+        let state = self.state.borrow();
+        let mut lcov = String::new();
+
+        let _ = writeln!(lcov, "TN:");
+        let _ = writeln!(lcov, "SF:{}", self.path);
+        for (line, name) in state.functions.keys() {
+            let _ = writeln!(lcov, "FN:{},{}", line, name);
+        }
+        for ((_, name), calls) in &state.functions {
+            let _ = writeln!(lcov, "FNDA:{},{}", calls, name);
+        }
+        let _ = writeln!(lcov, "FNF:{}", state.functions.len());
+        let _ = writeln!(lcov, "FNH:{}", count_hit(state.functions.values()));
+
+        for (block, ((line, _), branch)) in state.branches.iter().enumerate() {
+            for (i, taken) in branch.taken.iter().enumerate() {
+                // Branches on lines that never ran are reported as "-".
+                let taken = match state.lines.get(line) {
+                    Some(0) | None => "-".to_string(),
+                    _ => taken.to_string(),
+                };
+                let _ = writeln!(lcov, "BRDA:{},{},{},{}", line, block, i, taken);
+            }
+        }
+        let taken = state
+            .branches
+            .values()
+            .flat_map(|branch| branch.taken.iter());
+        let _ = writeln!(lcov, "BRF:{}", state.branches.len() * 2);
+        let _ = writeln!(lcov, "BRH:{}", count_hit(taken));
+
+        for (line, hits) in &state.lines {
+            let _ = writeln!(lcov, "DA:{},{}", line, hits);
+        }
+        let _ = writeln!(lcov, "LF:{}", state.lines.len());
+        let _ = writeln!(lcov, "LH:{}", count_hit(state.lines.values()));
+        let _ = writeln!(lcov, "end_of_record");
+
+        lcov
+    }
+
+    /// Line and branch totals followed by everything that never ran.
+    pub fn summary(&self) -> String {
+        let state = self.state.borrow();
+        let lines_hit = count_hit(state.lines.values());
+        let taken = state
+            .branches
+            .values()
+            .flat_map(|branch| branch.taken.iter());
+        let branches_hit = count_hit(taken);
+
+        let mut summary = String::new();
+        let _ = writeln!(
+            summary,
+            "{}: lines {}, branches {}",
+            self.path,
+            percent(lines_hit, state.lines.len()),
+            percent(branches_hit, state.branches.len() * 2)
+        );
+
+        for (line, _) in state.lines.iter().filter(|(_, hits)| **hits == 0) {
+            let _ = writeln!(summary, "  line {}: never run", line);
+        }
+        for ((line, _), branch) in &state.branches {
+            let labels = match branch.keyword.as_str() {
+                "if" => ["then branch", "else branch"],
+                _ => ["body", "exit"],
+            };
+            for (label, taken) in labels.iter().zip(branch.taken) {
+                if taken == 0 {
+                    let _ = writeln!(
+                        summary,
+                        "  line {}: '{}' {} never taken",
+                        line, branch.keyword, label
+                    );
+                }
+            }
+        }
+
+        summary
+    }
+}
+
+impl State {
+    /// Records every statement, function and branch, so code that never
+    /// runs still shows up with a count of zero.
+    fn register(&mut self, stmts: &[Statement]) {
+        for stmt in stmts {
+            self.register_stmt(stmt);
+        }
+    }
+
+    fn register_stmt(&mut self, stmt: &Statement) {
+        if let Some(line) = stmt.line() {
+            if !matches!(stmt, Statement::Block(_) | Statement::Comment(_, _)) {
+                self.lines.insert(line, 0);
+            }
+        }
+
+        match stmt {
+            Statement::Block(stmts) => self.register(stmts),
+            Statement::If(_, _, then_branch, else_branch) => {
+                self.register_branch(stmt);
+                self.register_stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.register_stmt(else_branch);
+                }
+            }
+            Statement::While(_, _, body) => {
+                self.register_branch(stmt);
+                self.register_stmt(body);
+            }
+            Statement::For(_, init, _, _, body) => {
+                self.register_branch(stmt);
+                if let Some(init) = init {
+                    self.register_stmt(init);
+                }
+                self.register_stmt(body);
+            }
+            Statement::Function(name, _, body) => {
+                self.functions.insert((name.3, name.1.clone()), 0);
+                self.register(body);
+            }
+            _ => {}
+        }
+    }
+
+    fn register_branch(&mut self, stmt: &Statement) {
+        if let Some(keyword) = branch_keyword(stmt) {
+            self.branches.insert(
+                (keyword.3, keyword.4),
+                Branch {
+                    keyword: keyword.1.clone(),
+                    taken: [0, 0],
+                },
+            );
+        }
+    }
+}
+
+impl Tracer for Coverage {
+    fn statement(&self, _interpreter: &Interpreter, stmt: &Statement) -> Result<(), RuntimeError> {
+        if let Some(line) = stmt.line() {
+            *self.state.borrow_mut().lines.entry(line).or_default() += 1;
+        }
+        Ok(())
+    }
+
+    fn branch(&self, stmt: &Statement, taken: bool) {
+        let Some(keyword) = branch_keyword(stmt) else {
+            return;
+        };
+        let mut state = self.state.borrow_mut();
+        if let Some(branch) = state.branches.get_mut(&(keyword.3, keyword.4)) {
+            branch.taken[if taken { 0 } else { 1 }] += 1;
+        }
+    }
+
+    fn call(&self, name: &str, line: usize) {
+        let mut state = self.state.borrow_mut();
+        *state.functions.entry((line, name.to_string())).or_default() += 1;
+    }
+}
+
+fn branch_keyword(stmt: &Statement) -> Option<&Token> {
+    match stmt {
+        Statement::If(keyword, _, _, _)
+        | Statement::While(keyword, _, _)
+        | Statement::For(keyword, _, _, _, _) => Some(keyword),
+        _ => None,
+    }
+}
+
+fn count_hit<'a>(counts: impl Iterator<Item = &'a u64>) -> usize {
+    counts.filter(|count| **count > 0).count()
+}
+
+fn percent(hit: usize, total: usize) -> String {
+    if total == 0 {
+        return "0/0".to_string();
+    }
+    format!(
+        "{}/{} ({:.1}%)",
+        hit,
+        total,
+        hit as f64 * 100.0 / total as f64
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::Coverage;
+    use crate::{
+        interpreter::Interpreter,
+        testing::{parse, run_with},
+    };
+
+    const SOURCE: &str = "\
+var a = 1;
+if (a > 0) print \"yes\";
+else print \"no\";
+fun f() {
+  return 1;
+}
+while (a < 3) a = a + 1;
+";
+
+    fn cover() -> Rc<Coverage> {
+        let stmts = parse(SOURCE);
+        let coverage = Rc::new(Coverage::new("test.lox", &stmts));
+        let interpreter = Interpreter::new().with_tracer(coverage.clone());
+        assert_eq!(run_with(interpreter, &stmts), "yes\n");
+        coverage
+    }
+
+    #[test]
+    fn writes_lcov() {
+        assert_eq!(
+            cover().lcov(),
+            "\
+TN:
+SF:test.lox
+FN:4,f
+FNDA:0,f
+FNF:1
+FNH:0
+BRDA:2,0,0,1
+BRDA:2,0,1,0
+BRDA:7,1,0,2
+BRDA:7,1,1,1
+BRF:4
+BRH:3
+DA:1,1
+DA:2,2
+DA:3,0
+DA:4,1
+DA:5,0
+DA:7,3
+LF:6
+LH:4
+end_of_record
+"
+        );
+    }
+
+    #[test]
+    fn summarizes_what_never_ran() {
+        assert_eq!(
+            cover().summary(),
+            "\
+test.lox: lines 4/6 (66.7%), branches 3/4 (75.0%)
+  line 3: never run
+  line 5: never run
+  line 2: 'if' else branch never taken
+"
+        );
+    }
+}
@@ -0,0 +1,378 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeSet,
+    fmt::Display,
+    io::{self, BufRead, Write},
+    rc::Rc,
+};
+
+use crate::{
+    interpreter::{stringify, Interpreter, Object, RuntimeError, Tracer},
+    lex::Tokenizer,
+    parse::{Parser, Statement},
+};
+
+const HELP: &str = "\
+Commands:
+  step, s          run to the next statement, entering calls
+  next, n          run to the next statement in this function
+  out, o           run until the current function returns
+  continue, c      run to the next breakpoint
+  break, b LINE    set a breakpoint
+  delete, d LINE   remove a breakpoint
+  vars, v          show the variables in every enclosing scope
+  print, p EXPR    evaluate an expression in the paused frame
+  list, l          show the source around the current line
+  quit, q          stop the program";
+
+/// An interactive debugger that pauses before statements and reads
+/// commands from stdin. It starts paused on the first statement.
+///
+/// The program shares stdin with the debugger, so `readLine` and `readAll`
+/// take lines that would otherwise be commands. Scripts that read input
+/// while debugged should be given it at the prompt, one line per read.
+pub struct Debugger {
+    lines: Vec<String>,
+    state: RefCell<State>,
+    /// Where commands come from, stdin unless replaced in tests.
+    input: Option<RefCell<Box<dyn BufRead>>>,
+    output: Rc<RefCell<dyn Write>>,
+}
+
+struct State {
+    mode: Mode,
+    breakpoints: BTreeSet<usize>,
+    /// Number of Lox calls currently on the stack.
+    depth: usize,
+    /// Line of the last statement run; breakpoints fire on entering a line.
+    line: Option<usize>,
+    /// Set while the prompt evaluates an expression, so calls made by it
+    /// run without pausing.
+    evaluating: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Continue,
+    Step,
+    /// Pause once the call depth is back to at most this.
+    Next(usize),
+    /// Pause once the call depth drops below this.
+    Out(usize),
+}
+
+impl Debugger {
+    pub fn new(source: &str) -> Self {
+        Debugger {
+            lines: source.lines().map(str::to_string).collect(),
+            state: RefCell::new(State {
+                mode: Mode::Step,
+                breakpoints: BTreeSet::new(),
+                depth: 0,
+                line: None,
+                evaluating: false,
+            }),
+            input: None,
+            output: Rc::new(RefCell::new(io::stdout())),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_io(
+        mut self,
+        input: impl BufRead + 'static,
+        output: Rc<RefCell<dyn Write>>,
+    ) -> Self {
+        self.input = Some(RefCell::new(Box::new(input)));
+        self.output = output;
+        self
+    }
+}
+
+impl Tracer for Debugger {
+    fn statement(&self, interpreter: &Interpreter, stmt: &Statement) -> Result<(), RuntimeError> {
+        let Some(line) = stmt.line() else {
+            return Ok(());
+        };
+
+        {
+            let mut state = self.state.borrow_mut();
+            if state.evaluating {
+                return Ok(());
+            }
+
+            let entered = state.line != Some(line);
+            state.line = Some(line);
+            let pause = match state.mode {
+                Mode::Step => true,
+                Mode::Next(depth) => state.depth <= depth,
+                Mode::Out(depth) => state.depth < depth,
+                Mode::Continue => false,
+            };
+            let breakpoint = entered && state.breakpoints.contains(&line);
+            if !(pause || breakpoint) {
+                return Ok(());
+            }
+        }
+
+        self.say(format_args!(
+            "Paused at line {}: {}",
+            line,
+            self.source(line).trim()
+        ));
+        self.prompt(interpreter, line);
+        Ok(())
+    }
+
+    fn call(&self, _name: &str, _line: usize) {
+        self.state.borrow_mut().depth += 1;
+    }
+
+    fn ret(&self) {
+        self.state.borrow_mut().depth -= 1;
+    }
+}
+
+impl Debugger {
+    /// Reads commands until one resumes the program.
+    fn prompt(&self, interpreter: &Interpreter, line: usize) {
+        loop {
+            {
+                let mut output = self.output.borrow_mut();
+                let _ = write!(output, "(lox) ");
+                let _ = output.flush();
+            }
+
+            let mut input = String::new();
+            if self.read_line(&mut input).unwrap_or(0) == 0 {
+                // Without input, let the program run to the end.
+                let mut state = self.state.borrow_mut();
+                state.mode = Mode::Continue;
+                state.breakpoints.clear();
+                self.say("");
+                return;
+            }
+
+            let (command, argument) = match input.trim().split_once(char::is_whitespace) {
+                Some((command, argument)) => (command, argument.trim()),
+                None => (input.trim(), ""),
+            };
+
+            let mut state = self.state.borrow_mut();
+            let mode = match command {
+                "step" | "s" => Mode::Step,
+                "next" | "n" => Mode::Next(state.depth),
+                "out" | "o" => Mode::Out(state.depth),
+                "continue" | "c" => Mode::Continue,
+                "break" | "b" => {
+                    match argument.parse() {
+                        Ok(line) => {
+                            state.breakpoints.insert(line);
+                            self.say(format_args!("Breakpoint set at line {}.", line));
+                        }
+                        Err(_) => self.say("Expected a line number."),
+                    }
+                    continue;
+                }
+                "delete" | "d" => {
+                    match argument.parse() {
+                        Ok(line) if state.breakpoints.remove(&line) => {
+                            self.say(format_args!("Breakpoint removed from line {}.", line))
+                        }
+                        Ok(line) => self.say(format_args!("No breakpoint at line {}.", line)),
+                        Err(_) => self.say("Expected a line number."),
+                    }
+                    continue;
+                }
+                "vars" | "v" => {
+                    self.print_scopes(interpreter);
+                    continue;
+                }
+                "print" | "p" => {
+                    state.evaluating = true;
+                    drop(state);
+                    self.print_expr(interpreter, argument);
+                    self.state.borrow_mut().evaluating = false;
+                    continue;
+                }
+                "list" | "l" => {
+                    self.list(line);
+                    continue;
+                }
+                "help" | "h" => {
+                    self.say(HELP);
+                    continue;
+                }
+                "quit" | "q" => std::process::exit(0),
+                "" => continue,
+                _ => {
+                    self.say(format_args!(
+                        "Unknown command '{}'. Type 'help' for a list.",
+                        command
+                    ));
+                    continue;
+                }
+            };
+
+            state.mode = mode;
+            return;
+        }
+    }
+
+    fn print_scopes(&self, interpreter: &Interpreter) {
+        let scopes = interpreter.scopes();
+        for (i, scope) in scopes.iter().enumerate() {
+            if i + 1 == scopes.len() {
+                self.say("globals:");
+            } else {
+                self.say(format_args!("scope {}:", i));
+            }
+            for (name, value) in scope {
+                // Natives are in every program, so only show them if rebound.
+                if let Object::Callable(callable) = value {
+                    if callable.is_native() && callable.name() == name {
+                        continue;
+                    }
+                }
+                self.say(format_args!("  {} = {}", name, stringify(value)));
+            }
+        }
+    }
+
+    fn print_expr(&self, interpreter: &Interpreter, source: &str) {
+        let (tokens, errors) = Tokenizer::new(source).tokenize();
+        if let Some(error) = errors.first() {
+            self.say(error);
+            return;
+        }
+
+        let (exprs, errors) = Parser::from_tokens(tokens).parse_expr();
+        if let Some(error) = errors.first() {
+            self.say(error);
+            return;
+        }
+
+        for expr in exprs.iter() {
+            match interpreter.evaluate(expr) {
+                Ok(value) => self.say(stringify(&value)),
+                Err(e) => self.say(e),
+            }
+        }
+    }
+
+    fn list(&self, line: usize) {
+        let first = line.saturating_sub(3).max(1);
+        let last = (line + 3).min(self.lines.len());
+        for number in first..=last {
+            let marker = if number == line { ">" } else { " " };
+            self.say(format_args!(
+                "{} {:>4} {}",
+                marker,
+                number,
+                self.source(number)
+            ));
+        }
+    }
+
+    fn read_line(&self, line: &mut String) -> io::Result<usize> {
+        match &self.input {
+            Some(input) => input.borrow_mut().read_line(line),
+            None => io::stdin().lock().read_line(line),
+        }
+    }
+
+    fn say(&self, text: impl Display) {
+        let _ = writeln!(self.output.borrow_mut(), "{}", text);
+    }
+
+    fn source(&self, line: usize) -> &str {
+        self.lines
+            .get(line.wrapping_sub(1))
+            .map_or("", String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, io::Cursor, rc::Rc};
+
+    use super::Debugger;
+    use crate::{interpreter::Interpreter, testing::parse};
+
+    const SOURCE: &str = "\
+var a = \"x\";
+fun f(n) {
+  print n;
+}
+f(1);
+f(2);
+print a;
+";
+
+    /// The transcript of debugging `SOURCE` with the given commands.
+    fn debug(commands: &str) -> String {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let debugger =
+            Debugger::new(SOURCE).with_io(Cursor::new(commands.to_string()), output.clone());
+        Interpreter::new()
+            .with_output(output.clone())
+            .with_tracer(Rc::new(debugger))
+            .interpret(&parse(SOURCE))
+            .unwrap();
+        String::from_utf8(output.take()).unwrap()
+    }
+
+    #[test]
+    fn steps_into_calls() {
+        assert_eq!(
+            debug("s\ns\ns\nc\n"),
+            "\
+Paused at line 1: var a = \"x\";
+(lox) Paused at line 2: fun f(n) {
+(lox) Paused at line 5: f(1);
+(lox) Paused at line 3: print n;
+(lox) 1
+2
+x
+"
+        );
+    }
+
+    #[test]
+    fn continues_to_breakpoints() {
+        assert_eq!(
+            debug("b 3\nc\nv\nc\nd 3\nc\n"),
+            "\
+Paused at line 1: var a = \"x\";
+(lox) Breakpoint set at line 3.
+(lox) Paused at line 3: print n;
+(lox) scope 0:
+  n = 1
+globals:
+  a = x
+  f = <fn f>
+(lox) 1
+Paused at line 3: print n;
+(lox) Breakpoint removed from line 3.
+(lox) 2
+x
+"
+        );
+    }
+
+    #[test]
+    fn runs_to_the_end_without_input() {
+        assert_eq!(
+            debug("s\np a + \"y\"\n"),
+            "\
+Paused at line 1: var a = \"x\";
+(lox) Paused at line 2: fun f(n) {
+(lox) xy
+(lox) 
+1
+2
+x
+"
+        );
+    }
+}
@@ -0,0 +1,406 @@
+use crate::{
+    interpreter::Object,
+    parse::{Expr, Statement},
+};
+
+const INDENT: &str = "  ";
+
+/// Pretty-prints a parsed program back into Lox source.
+pub struct Formatter {
+    lines: Vec<String>,
+    depth: usize,
+    /// Whether the last line is a comment or ends in a line comment, which
+    /// nothing may follow.
+    commented: bool,
+}
+
+impl Formatter {
+    pub fn format(stmts: &[Statement]) -> String {
+        let mut formatter = Formatter {
+            lines: Vec::new(),
+            depth: 0,
+            commented: false,
+        };
+        formatter.statements(stmts);
+
+        let mut output = formatter.lines.join("\n");
+        if !output.is_empty() {
+            output.push('\n');
+        }
+        output
+    }
+
+    /// Whether `source` is already laid out the way `format` would write it.
+    pub fn is_formatted(source: &str, stmts: &[Statement]) -> bool {
+        Formatter::format(stmts) == source
+    }
+}
+
+impl Formatter {
+    fn statements(&mut self, stmts: &[Statement]) {
+        let mut prev: Option<&Statement> = None;
+
+        for (i, stmt) in stmts.iter().enumerate() {
+            if let Statement::Comment(token, true) = stmt {
+                self.trailing(&token.1);
+                continue;
+            }
+
+            // Function declarations, together with the comments leading
+            // them, are separated from their neighbours by a blank line.
+            let separate = match prev {
+                None | Some(Statement::Comment(_, _)) => false,
+                Some(Statement::Function(_, _, _)) => true,
+                Some(_) => starts_function(&stmts[i..]),
+            };
+            if separate {
+                self.lines.push(String::new());
+            }
+
+            self.statement(stmt);
+            prev = Some(stmt);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Block(stmts) => self.braced(String::new(), stmts),
+            Statement::If(_, cond, then_branch, else_branch) => {
+                self.if_statement("", cond, then_branch, else_branch.as_deref())
+            }
+            Statement::While(_, cond, body) => self.body(format!("while ({})", expr(cond)), body),
+            Statement::For(_, init, cond, increment, body) => {
+                let init = match init {
+                    Some(init) => simple(init).unwrap_or_default(),
+                    None => ";".to_string(),
+                };
+                let cond = match cond {
+                    Some(cond) => format!(" {};", expr(cond)),
+                    None => ";".to_string(),
+                };
+                let increment = match increment {
+                    Some(increment) => format!(" {}", expr(increment)),
+                    None => String::new(),
+                };
+                self.body(format!("for ({}{}{})", init, cond, increment), body);
+            }
+            Statement::Function(name, params, body) => {
+                let params: Vec<&str> = params.iter().map(|param| param.1.as_str()).collect();
+                self.braced(format!("fun {}({})", name.1, params.join(", ")), body);
+            }
+            Statement::Comment(token, _) => {
+                self.line(token.1.clone());
+                self.commented = true;
+            }
+            _ => {
+                if let Some(line) = simple(stmt) {
+                    self.line(line);
+                }
+            }
+        }
+    }
+
+    fn if_statement(
+        &mut self,
+        prefix: &str,
+        cond: &Expr,
+        then_branch: &Statement,
+        else_branch: Option<&Statement>,
+    ) {
+        self.body(format!("{}if ({})", prefix, expr(cond)), then_branch);
+
+        let Some(else_branch) = else_branch else {
+            return;
+        };
+
+        // `else` shares the closing brace's line when the then branch is a block.
+        let prefix = if let Statement::Block(_) = then_branch {
+            self.lines.pop();
+            "} else"
+        } else {
+            "else"
+        };
+
+        match else_branch {
+            Statement::If(_, cond, then_branch, else_branch) => self.if_statement(
+                &format!("{} ", prefix),
+                cond,
+                then_branch,
+                else_branch.as_deref(),
+            ),
+            _ => self.body(prefix.to_string(), else_branch),
+        }
+    }
+
+    /// Writes a statement that follows a header such as `while (...)`,
+    /// keeping blocks on the header's line and indenting anything else.
+    fn body(&mut self, header: String, body: &Statement) {
+        if let Statement::Block(stmts) = body {
+            self.braced(header, stmts);
+        } else {
+            self.line(header);
+            self.depth += 1;
+            self.statement(body);
+            self.depth -= 1;
+        }
+    }
+
+    fn braced(&mut self, header: String, stmts: &[Statement]) {
+        let open = if header.is_empty() {
+            "{".to_string()
+        } else {
+            format!("{} {{", header)
+        };
+
+        if stmts.is_empty() {
+            self.line(format!("{}}}", open));
+            return;
+        }
+
+        self.line(open);
+        self.depth += 1;
+        self.statements(stmts);
+        self.depth -= 1;
+        self.line("}".to_string());
+    }
+
+    fn trailing(&mut self, comment: &str) {
+        match self.lines.last_mut() {
+            Some(last) if !last.is_empty() && !self.commented => {
+                last.push(' ');
+                last.push_str(comment);
+            }
+            _ => self.line(comment.to_string()),
+        }
+        self.commented = comment.starts_with("//");
+    }
+
+    fn line(&mut self, line: String) {
+        // Calls with commented arguments span several lines.
+        for line in line.split('\n') {
+            self.lines
+                .push(format!("{}{}", INDENT.repeat(self.depth), line));
+        }
+        self.commented = false;
+    }
+}
+
+/// Renders statements that always fit on a single line.
+fn simple(stmt: &Statement) -> Option<String> {
+    let line = match stmt {
+        Statement::Expression(e) => format!("{};", expr(e)),
+        Statement::Print(_, e) => format!("print {};", expr(e)),
+        Statement::Var(name, Some(init)) => format!("var {} = {};", name.1, expr(init)),
+        Statement::Var(name, None) => format!("var {};", name.1),
+        Statement::Return(keyword, Some(value)) => format!("{} {};", keyword.1, expr(value)),
+        Statement::Return(keyword, None) => format!("{};", keyword.1),
+        _ => return None,
+    };
+
+    Some(line)
+}
+
+fn starts_function(stmts: &[Statement]) -> bool {
+    matches!(
+        stmts
+            .iter()
+            .find(|stmt| !matches!(stmt, Statement::Comment(_, _))),
+        Some(Statement::Function(_, _, _))
+    )
+}
+
+fn expr(expr: &Expr) -> String {
+    match expr {
+        // Literals keep the form they were written in, such as `0xFF` or
+        // `"\u{48}"`. Strings spanning lines are escaped instead, since
+        // continuation lines get re-indented.
+        Expr::Literal(_, Some(lexeme)) if !lexeme.contains('\n') => lexeme.clone(),
+        Expr::Literal(Object::String(s), _) => format!("\"{}\"", escape(s)),
+        // Debug formatting keeps the fraction, so floats don't turn into integers.
+        Expr::Literal(Object::Number(n), _) => format!("{:?}", n),
+        Expr::Literal(Object::Integer(n), _) => n.to_string(),
+        Expr::Literal(obj, _) => obj.to_string(),
+        Expr::Unary(op, right) => {
+            // Keep `- -x` from being read back as a decrement.
+            let right = self::expr(right);
+            let space = if right.starts_with(op.1.as_str()) {
+                " "
+            } else {
+                ""
+            };
+            format!("{}{}{}", op.1, space, right)
+        }
+        Expr::Binary(left, op, right) | Expr::Logical(left, op, right) => {
+            format!("{} {} {}", self::expr(left), op.1, self::expr(right))
+        }
+        Expr::Conditional(cond, then_expr, else_expr) => format!(
+            "{} ? {} : {}",
+            self::expr(cond),
+            self::expr(then_expr),
+            self::expr(else_expr)
+        ),
+        Expr::Grouping(inner) => format!("({})", self::expr(inner)),
+        Expr::Variable(name) => name.1.to_string(),
+        Expr::Assign(name, value) => format!("{} = {}", name.1, self::expr(value)),
+        Expr::CompoundAssign(name, op, value) => {
+            format!("{} {} {}", name.1, op.1, self::expr(value))
+        }
+        Expr::Prefix(op, name) => format!("{}{}", op.1, name.1),
+        Expr::Postfix(name, op) => format!("{}{}", name.1, op.1),
+        Expr::Call(callee, _, args) if args.iter().any(is_commented) => {
+            // One argument per line, so each comment stays after its argument.
+            let mut output = format!("{}(", self::expr(callee));
+            for (i, arg) in args.iter().enumerate() {
+                let (arg, comments) = match arg {
+                    Expr::Commented(arg, comments) => (arg.as_ref(), comments.as_slice()),
+                    arg => (arg, &[][..]),
+                };
+                let comma = if i + 1 < args.len() { "," } else { "" };
+                let arg = self::expr(arg).replace('\n', &format!("\n{}", INDENT));
+                output.push_str(&format!("\n{}{}{}", INDENT, arg, comma));
+                for (j, comment) in comments.iter().enumerate() {
+                    let separator = if j == 0 {
+                        " ".to_string()
+                    } else {
+                        format!("\n{}", INDENT)
+                    };
+                    output.push_str(&format!("{}{}", separator, comment.1));
+                }
+            }
+            output.push_str("\n)");
+            output
+        }
+        Expr::Call(callee, _, args) => {
+            let args: Vec<String> = args.iter().map(self::expr).collect();
+            format!("{}({})", self::expr(callee), args.join(", "))
+        }
+        Expr::Interpolation(parts) => {
+            let mut output = String::from('"');
+            for (i, part) in parts.iter().enumerate() {
+                match part {
+                    Expr::Literal(_, Some(lexeme)) if i % 2 == 0 && !lexeme.contains('\n') => {
+                        output.push_str(lexeme)
+                    }
+                    Expr::Literal(Object::String(s), _) if i % 2 == 0 => {
+                        output.push_str(&escape(s))
+                    }
+                    part => output.push_str(&format!("${{{}}}", self::expr(part))),
+                }
+            }
+            output.push('"');
+            output
+        }
+        Expr::Commented(expr, _) => self::expr(expr),
+    }
+}
+
+fn is_commented(expr: &Expr) -> bool {
+    matches!(expr, Expr::Commented(_, _))
+}
+
+/// Writes a string value back in its source form, using escapes for
+/// characters that can't appear literally.
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{{{:04X}}}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.replace("${", "\\${")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Formatter;
+    use crate::testing::parse_with_comments;
+
+    fn format(source: &str) -> String {
+        Formatter::format(&parse_with_comments(source))
+    }
+
+    /// Sources exercising every statement form and comment position.
+    const SOURCES: &[&str] = &[
+        "if (a) // why\n  print 1;\nelse print 2; // two\n",
+        "foo(1, // first\n2); // call\n",
+        "bar(/* a */ 1, g(2, // inner\n3));\n",
+        "{ // open\nprint 3; /* x */ // y\n}\n",
+        "var x = 1 + // one\n2;\n",
+        "fun f(a, b) { return a; } // f\nwhile (x) { x = x - 1; }\n",
+        "for (var i = 0; i < 3; i = i + 1) print i; // loop\n",
+        "if (a) { print 1; } else if (b) print 2; else { print 3; }\n",
+    ];
+
+    #[test]
+    fn places_comments() {
+        assert_eq!(
+            format(SOURCES[0]),
+            "// why\nif (a)\n  print 1;\nelse\n  print 2; // two\n"
+        );
+        assert_eq!(format(SOURCES[1]), "foo(\n  1, // first\n  2\n); // call\n");
+        assert_eq!(
+            format(SOURCES[2]),
+            "bar(\n  1, /* a */\n  g(\n    2, // inner\n    3\n  )\n);\n"
+        );
+        assert_eq!(
+            format(SOURCES[3]),
+            SOURCES[3].replace("\nprint", "\n  print")
+        );
+        assert_eq!(format(SOURCES[4]), "// one\nvar x = 1 + 2;\n");
+    }
+
+    #[test]
+    fn never_trails_a_line_comment() {
+        assert_eq!(
+            format("/* a */ /* b */\nprint 1;\n"),
+            "/* a */\n/* b */\nprint 1;\n"
+        );
+        assert_eq!(
+            format("if (a) // a\nprint 1; // b\n"),
+            "// a\nif (a)\n  print 1; // b\n"
+        );
+    }
+
+    #[test]
+    fn is_idempotent() {
+        for source in SOURCES {
+            let once = format(source);
+            assert_eq!(format(&once), once, "{}", source);
+        }
+    }
+
+    #[test]
+    fn checks_formatting() {
+        for source in SOURCES {
+            let formatted = format(source);
+            assert!(Formatter::is_formatted(
+                &formatted,
+                &parse_with_comments(&formatted)
+            ));
+        }
+        let source = "print   1;\n";
+        assert!(!Formatter::is_formatted(
+            source,
+            &parse_with_comments(source)
+        ));
+    }
+
+    #[test]
+    fn keeps_number_lexemes() {
+        let source = "print 0xFF + 0b1010 + 1_000_000 + 1.5e-3 + 2.50 + 7;\n";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn keeps_string_lexemes() {
+        let source = "print \"a\\u{48}\\t\" + \"x\\u{41}${a}\\$y${a + 1}\\\"z\";\n";
+        assert_eq!(format(source), source);
+        assert_eq!(format("print \"a\nb\";"), "print \"a\\nb\";\n");
+    }
+}
@@ -2,28 +2,34 @@ use std::fmt::Display;
 
 use crate::lex::Token;
 
-use super::{Environment, Interpreter, Object, RuntimeError, Statement};
+use super::{natives::Native, Environment, Interpreter, Object, RuntimeError, Statement};
 
 pub trait LoxCallable {
     fn name(&self) -> &str;
-    #[allow(dead_code)]
     fn arity(&self) -> usize;
     fn call(&self, interpreter: &Interpreter, arguments: &[Object])
         -> Result<Object, RuntimeError>;
+
+    /// Whether this is one of the functions built into the interpreter.
+    fn is_native(&self) -> bool {
+        false
+    }
 }
 
 #[derive(Clone)]
 pub struct LoxFunction {
     name: String,
+    line: usize,
     params: Vec<Token>,
     body: Vec<Statement>,
     closure: Environment,
 }
 
 impl LoxFunction {
-    pub fn new(name: &str, params: &[Token], body: &[Statement], closure: &Environment) -> Self {
+    pub fn new(name: &Token, params: &[Token], body: &[Statement], closure: &Environment) -> Self {
         LoxFunction {
-            name: name.to_string(),
+            name: name.1.clone(),
+            line: name.3,
             params: params.to_vec(),
             body: body.to_vec(),
             closure: closure.clone(),
@@ -31,6 +37,18 @@ impl LoxFunction {
     }
 }
 
+impl LoxFunction {
+    fn run(&self, interpreter: &Interpreter) -> Result<Object, RuntimeError> {
+        for stmt in self.body.iter() {
+            if let Some(result) = interpreter.interpret_stmt(stmt)? {
+                return Ok(result);
+            }
+        }
+
+        Ok(Object::Nil)
+    }
+}
+
 impl LoxCallable for LoxFunction {
     fn name(&self) -> &str {
         &self.name
@@ -42,52 +60,60 @@ impl LoxCallable for LoxFunction {
 
     fn call(
         &self,
-        _interpreter: &Interpreter,
+        interpreter: &Interpreter,
         arguments: &[Object],
     ) -> Result<Object, RuntimeError> {
         let env = Environment::new_enclosed(&self.closure);
-        if self.params.len() != arguments.len() {
-            return Err(RuntimeError::TypeError(format!(
-                "Expected {} arguments but got {}.",
-                self.params.len(),
-                arguments.len()
-            )));
-        }
         for (param, arg) in self.params.iter().zip(arguments.iter()) {
             env.define(param.1.clone(), arg.clone());
         }
 
-        let interpreter = Interpreter { env };
-        for stmt in self.body.iter() {
-            if let Some(result) = interpreter.interpret_stmt(stmt)? {
-                return Ok(result);
+        let interpreter = interpreter.enclosed(env);
+        interpreter.budget.enter()?;
+        let result = match &interpreter.tracer {
+            Some(tracer) => {
+                tracer.call(&self.name, self.line);
+                let result = self.run(&interpreter);
+                tracer.ret();
+                result
             }
-        }
-
-        Ok(Object::Nil)
+            None => self.run(&interpreter),
+        };
+        interpreter.budget.leave();
+        result
     }
 }
 
 pub(super) struct NativeFunction {
-    pub name: String,
-    pub func: fn() -> Result<Object, RuntimeError>,
+    pub native: &'static Native,
+    /// Whether the interpreter was granted the native's capability.
+    pub allowed: bool,
 }
 
 impl LoxCallable for NativeFunction {
     fn name(&self) -> &str {
-        &self.name
+        self.native.name
     }
 
     fn arity(&self) -> usize {
-        0
+        self.native.arity
     }
 
     fn call(
         &self,
-        _interpreter: &Interpreter,
-        _arguments: &[Object],
+        interpreter: &Interpreter,
+        arguments: &[Object],
     ) -> Result<Object, RuntimeError> {
-        (self.func)()
+        match self.native.capability {
+            Some(capability) if !self.allowed => {
+                Err(RuntimeError::Denied(self.native.name, capability))
+            }
+            _ => (self.native.func)(interpreter, arguments),
+        }
+    }
+
+    fn is_native(&self) -> bool {
+        true
     }
 }
 
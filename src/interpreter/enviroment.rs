@@ -1,6 +1,6 @@
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
-use super::{RuntimeError, Object};
+use super::{Object, RuntimeError};
 
 #[derive(Clone)]
 pub(super) struct Environment {
@@ -34,6 +34,23 @@ impl Environment {
     pub fn assign(&self, name: &str, value: Object) -> Result<(), RuntimeError> {
         self.inner.borrow_mut().assign(name, value)
     }
+
+    /// Every scope's variables sorted by name, innermost scope first.
+    pub fn scopes(&self) -> Vec<Vec<(String, Object)>> {
+        let inner = self.inner.borrow();
+        let mut variables: Vec<_> = inner
+            .values
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        variables.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut scopes = vec![variables];
+        if let Some(enclosing) = &inner.enclosing {
+            scopes.extend(enclosing.scopes());
+        }
+        scopes
+    }
 }
 
 struct EnvironmentImpl {
@@ -0,0 +1,174 @@
+use std::{
+    cell::Cell,
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use super::RuntimeError;
+
+/// How often, in statements, the clock is checked against the deadline.
+const CLOCK_INTERVAL: u64 = 256;
+
+/// Stack kept free for the frames of the call being entered.
+const STACK_MARGIN: usize = 256 * 1024;
+
+/// The stack Rust gives spawned threads, and less than a main thread has,
+/// so calls stop with an error rather than a crash on either by default.
+const DEFAULT_STACK_SIZE: usize = 2 * 1024 * 1024;
+
+/// Bounds on the work a program may do. Exceeding any of them stops the
+/// program with a runtime error.
+#[derive(Clone, Copy)]
+pub struct Limits {
+    /// Deepest nesting of Lox function calls. Calls also stop short of
+    /// `stack_size`, however deep they are.
+    pub max_depth: usize,
+    /// Stack of the thread that runs the interpreter, counted from where
+    /// the interpreter is created or `Interpreter::with_limits` is called.
+    /// `None` leaves only `max_depth` to stop deep recursion.
+    pub stack_size: Option<usize>,
+    /// Most statements the program may execute.
+    pub max_steps: Option<u64>,
+    /// Longest the program may run, counted from `Interpreter::with_limits`.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_depth: 10_000,
+            stack_size: Some(DEFAULT_STACK_SIZE),
+            max_steps: None,
+            timeout: None,
+        }
+    }
+}
+
+/// Tracks a running program against its limits.
+pub(super) struct Budget {
+    limits: Limits,
+    deadline: Option<Instant>,
+    /// Address near the bottom of the interpreter's stack.
+    stack_base: usize,
+    depth: Cell<usize>,
+    steps: Cell<u64>,
+}
+
+impl Budget {
+    pub fn new(limits: Limits) -> Self {
+        Budget {
+            limits,
+            deadline: limits.timeout.map(|timeout| Instant::now() + timeout),
+            stack_base: stack_address(),
+            depth: Cell::new(0),
+            steps: Cell::new(0),
+        }
+    }
+
+    /// Counts one executed statement.
+    pub fn step(&self) -> Result<(), RuntimeError> {
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+
+        if matches!(self.limits.max_steps, Some(max) if steps > max) {
+            return Err(RuntimeError::StepLimit);
+        }
+        if let Some(deadline) = self.deadline {
+            if steps % CLOCK_INTERVAL == 0 && Instant::now() >= deadline {
+                return Err(RuntimeError::Timeout);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Counts a function call, which must be paired with `leave`.
+    pub fn enter(&self) -> Result<(), RuntimeError> {
+        if self.depth.get() >= self.limits.max_depth {
+            return Err(RuntimeError::StackOverflow);
+        }
+        if let Some(stack_size) = self.limits.stack_size {
+            let used = self.stack_base.abs_diff(stack_address());
+            if used + STACK_MARGIN > stack_size {
+                return Err(RuntimeError::StackOverflow);
+            }
+        }
+        self.depth.set(self.depth.get() + 1);
+        Ok(())
+    }
+
+    pub fn leave(&self) {
+        self.depth.set(self.depth.get() - 1);
+    }
+}
+
+/// The address of a local, which tracks how deep the stack is.
+fn stack_address() -> usize {
+    let marker = 0u8;
+    black_box(&marker) as *const u8 as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Limits;
+    use crate::{
+        interpreter::Interpreter,
+        testing::{parse, run_with},
+    };
+
+    const RECURSE: &str = "fun f(n) { if (n > 0) f(n - 1); } f(N); print \"done\";";
+
+    fn run(limits: Limits, source: &str) -> String {
+        run_with(Interpreter::new().with_limits(limits), &parse(source))
+    }
+
+    #[test]
+    fn default_stops_before_the_stack_runs_out() {
+        let source = RECURSE.replace('N', "1000000");
+        let output = run_with(Interpreter::new(), &parse(&source));
+        assert_eq!(output, "error: Stack overflow.\n");
+
+        let source = RECURSE.replace('N', "50");
+        assert_eq!(run_with(Interpreter::new(), &parse(&source)), "done\n");
+    }
+
+    #[test]
+    fn stops_at_max_depth() {
+        let limits = Limits {
+            max_depth: 20,
+            ..Limits::default()
+        };
+        assert_eq!(run(limits, &RECURSE.replace('N', "19")), "done\n");
+        assert_eq!(
+            run(limits, &RECURSE.replace('N', "20")),
+            "error: Stack overflow.\n"
+        );
+    }
+
+    #[test]
+    fn stops_after_max_steps() {
+        let limits = Limits {
+            max_steps: Some(5),
+            ..Limits::default()
+        };
+        assert_eq!(run(limits, "print 1; print 2; print 3;"), "1\n2\n3\n");
+        assert_eq!(
+            run(limits, "for (var i = 1; ; i = i + 1) print i;"),
+            "1\n2\n3\nerror: Step limit exceeded.\n"
+        );
+    }
+
+    #[test]
+    fn stops_at_the_timeout() {
+        let limits = Limits {
+            timeout: Some(Duration::ZERO),
+            ..Limits::default()
+        };
+        assert_eq!(
+            run(limits, "while (true) {}"),
+            "error: Execution timed out.\n"
+        );
+    }
+}
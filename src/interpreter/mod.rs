@@ -1,13 +1,26 @@
 mod callable;
 mod enviroment;
+mod limits;
+mod natives;
 mod object;
+mod stringify;
+mod tracer;
 
-use std::{rc::Rc, time::SystemTime};
+use std::{
+    cell::RefCell,
+    io::{self, BufRead, Write},
+    rc::Rc,
+};
 
 use callable::NativeFunction;
 use enviroment::Environment;
+use limits::Budget;
+pub use limits::Limits;
+pub use natives::Capability;
 pub use object::Object;
+pub use stringify::stringify;
 use thiserror::Error;
+pub use tracer::Tracer;
 
 use crate::{
     lex::{Token, TokenType},
@@ -20,30 +33,128 @@ pub enum RuntimeError {
     TypeError(String),
     #[error("Undefined variable '{0}'.")]
     UndefinedVariable(String),
+    #[error("Integer overflow.")]
+    Overflow,
+    #[error("Division by zero.")]
+    DivisionByZero,
+    #[error("Shift amount {0} is out of range.")]
+    ShiftAmount(i64),
+    #[error("Operand must be a number.")]
+    NumberOperand(TokenType, &'static str),
+    #[error("Operand must be an integer.")]
+    IntegerOperand(TokenType, &'static str),
+    #[error("Operands must be numbers.")]
+    NumberOperands(TokenType, &'static str, &'static str),
+    #[error("Operands must be integers.")]
+    IntegerOperands(TokenType, &'static str, &'static str),
+    #[error("Operands must be two numbers or two strings.")]
+    AddOperands(&'static str, &'static str),
+    #[error("Stack overflow.")]
+    StackOverflow,
+    #[error("Step limit exceeded.")]
+    StepLimit,
+    #[error("Execution timed out.")]
+    Timeout,
+    #[error("'{0}' needs the {1} capability.")]
+    Denied(&'static str, Capability),
+    #[error("Arguments to '{0}' must be {1}.")]
+    ArgumentType(&'static str, &'static str),
+    #[error("Index {0} is out of range.")]
+    IndexOutOfRange(i64),
+    #[error("{0}")]
+    Io(String),
+    /// Raised by `exit` to unwind the whole program.
+    #[error("Exited with code {0}.")]
+    Exit(i32),
 }
 
 pub struct Interpreter {
     env: Environment,
+    tracer: Option<Rc<dyn Tracer>>,
+    budget: Rc<Budget>,
+    /// What the `args` native returns.
+    args: Rc<[String]>,
+    /// Where `print` writes.
+    output: Rc<RefCell<dyn Write>>,
+    /// What `readLine` and `readAll` read, stdin unless replaced in tests.
+    input: Option<Rc<RefCell<dyn BufRead>>>,
 }
 
 impl Interpreter {
     pub fn new() -> Self {
+        Interpreter::with_capabilities(&Capability::DEFAULT)
+    }
+
+    /// An interpreter whose scripts may only call the natives in the given
+    /// capability groups, plus those that need none. The rest are still
+    /// defined but fail when called.
+    pub fn with_capabilities(capabilities: &[Capability]) -> Self {
         let env = Environment::new();
-        env.define(
-            "clock".to_string(),
-            Object::Callable(Rc::new(NativeFunction {
-                name: "clock".to_string(),
-                func: || {
-                    Ok(Object::Number(
-                        SystemTime::now()
-                            .duration_since(SystemTime::UNIX_EPOCH)
-                            .unwrap()
-                            .as_secs() as f64,
-                    ))
-                },
-            })),
-        );
-        Interpreter { env }
+        for native in natives::NATIVES {
+            let allowed = native
+                .capability
+                .map_or(true, |capability| capabilities.contains(&capability));
+            env.define(
+                native.name.to_string(),
+                Object::Callable(Rc::new(NativeFunction { native, allowed })),
+            );
+        }
+        Interpreter {
+            env,
+            tracer: None,
+            budget: Rc::new(Budget::new(Limits::default())),
+            args: Rc::from([]),
+            output: Rc::new(RefCell::new(io::stdout())),
+            input: None,
+        }
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.budget = Rc::new(Budget::new(limits));
+        self
+    }
+
+    /// Sets the command-line arguments a script sees through `args`.
+    pub fn with_args(mut self, args: &[String]) -> Self {
+        self.args = Rc::from(args);
+        self
+    }
+
+    /// Sends the output of `print` somewhere other than stdout.
+    #[cfg(test)]
+    pub fn with_output(mut self, output: Rc<RefCell<dyn Write>>) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Has `readLine` and `readAll` read something other than stdin.
+    #[cfg(test)]
+    pub fn with_input(mut self, input: impl BufRead + 'static) -> Self {
+        self.input = Some(Rc::new(RefCell::new(input)));
+        self
+    }
+
+    pub fn with_tracer(mut self, tracer: Rc<dyn Tracer>) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    /// An interpreter for a nested scope that shares this one's tracer,
+    /// limits, arguments, input and output.
+    fn enclosed(&self, env: Environment) -> Interpreter {
+        Interpreter {
+            env,
+            tracer: self.tracer.clone(),
+            budget: self.budget.clone(),
+            args: self.args.clone(),
+            output: self.output.clone(),
+            input: self.input.clone(),
+        }
+    }
+
+    /// The variables visible from here, innermost scope first.
+    pub fn scopes(&self) -> Vec<Vec<(String, Object)>> {
+        self.env.scopes()
     }
 }
 
@@ -59,32 +170,47 @@ impl Interpreter {
     }
 
     fn interpret_stmt(&self, stmt: &Statement) -> Result<Option<Object>, RuntimeError> {
+        self.budget.step()?;
+
+        let tracer = match &self.tracer {
+            Some(tracer) if !matches!(stmt, Statement::Block(_) | Statement::Comment(_, _)) => {
+                tracer
+            }
+            _ => return self.execute(stmt),
+        };
+
+        tracer.statement(self, stmt)?;
+        let result = self.execute(stmt);
+        tracer.statement_end(stmt);
+        result
+    }
+
+    fn execute(&self, stmt: &Statement) -> Result<Option<Object>, RuntimeError> {
         match stmt {
             Statement::Expression(expr) => {
                 self.evaluate(expr)?;
             }
-            Statement::Print(expr) => {
+            Statement::Print(_, expr) => {
                 let value = self.evaluate(expr)?;
-                println!("{}", self.stringify(&value));
+                self.print(&value)?;
             }
             Statement::Var(name, init) => {
                 let value = match init {
                     Some(expr) => self.evaluate(expr)?,
                     None => Object::Nil,
                 };
-                self.env.define(name.clone(), value);
+                self.env.define(name.1.clone(), value);
             }
             Statement::Block(stmts) => {
-                let env = Environment::new_enclosed(&self.env);
-                let interpreter = Interpreter { env };
-                match interpreter.interpret(stmts)? {
-                    Some(value) => return Ok(Some(value)),
-                    None => {}
+                let interpreter = self.enclosed(Environment::new_enclosed(&self.env));
+                if let Some(value) = interpreter.interpret(stmts)? {
+                    return Ok(Some(value));
                 }
             }
-            Statement::If(cond, then_branch, else_branch) => {
-                let cond = self.evaluate(cond)?;
-                let value = if self.truthy(&cond) {
+            Statement::If(_, cond, then_branch, else_branch) => {
+                let taken = self.truthy(&self.evaluate(cond)?);
+                self.trace_branch(stmt, taken);
+                let value = if taken {
                     self.interpret_stmt(then_branch)?
                 } else if let Some(else_branch) = else_branch {
                     self.interpret_stmt(else_branch)?
@@ -96,17 +222,42 @@ impl Interpreter {
                     return Ok(Some(value));
                 }
             }
-            Statement::While(cond, body) => {
-                while self.truthy(&self.evaluate(cond)?) {
-                    if let Some(value) = self.interpret_stmt(body)? {
+            Statement::While(_, cond, body) => loop {
+                let taken = self.truthy(&self.evaluate(cond)?);
+                self.trace_branch(stmt, taken);
+                if !taken {
+                    break;
+                }
+                if let Some(value) = self.interpret_stmt(body)? {
+                    return Ok(Some(value));
+                }
+            },
+            Statement::For(_, init, cond, increment, body) => {
+                let interpreter = self.enclosed(Environment::new_enclosed(&self.env));
+                if let Some(init) = init {
+                    interpreter.interpret_stmt(init)?;
+                }
+                loop {
+                    let taken = match cond {
+                        Some(cond) => self.truthy(&interpreter.evaluate(cond)?),
+                        None => true,
+                    };
+                    self.trace_branch(stmt, taken);
+                    if !taken {
+                        break;
+                    }
+                    if let Some(value) = interpreter.interpret_stmt(body)? {
                         return Ok(Some(value));
                     }
+                    if let Some(increment) = increment {
+                        interpreter.evaluate(increment)?;
+                    }
                 }
             }
             Statement::Function(name, params, body) => {
                 let func = callable::LoxFunction::new(name, params, body, &self.env);
                 self.env
-                    .define(name.clone(), Object::Callable(Rc::new(func)));
+                    .define(name.1.clone(), Object::Callable(Rc::new(func)));
             }
             Statement::Return(_, expr) => {
                 let value = match expr {
@@ -115,16 +266,15 @@ impl Interpreter {
                 };
                 return Ok(Some(value));
             }
+            Statement::Comment(_, _) => {}
         };
 
         Ok(None) // no return value
     }
 
-    fn stringify(&self, obj: &Object) -> String {
-        if let Object::Number(n) = obj {
-            n.to_string()
-        } else {
-            obj.to_string()
+    fn trace_branch(&self, stmt: &Statement, taken: bool) {
+        if let Some(tracer) = &self.tracer {
+            tracer.branch(stmt, taken);
         }
     }
 }
@@ -132,25 +282,59 @@ impl Interpreter {
 impl Interpreter {
     pub fn eval(&self, expr: &Expr) -> Result<(), RuntimeError> {
         let value = self.evaluate(expr)?;
-        println!("{}", self.stringify(&value));
+        self.print(&value)
+    }
+
+    /// Runs `read` on the interpreter's input.
+    fn read_input<T>(&self, read: impl FnOnce(&mut dyn BufRead) -> io::Result<T>) -> io::Result<T> {
+        match &self.input {
+            Some(input) => read(&mut *input.borrow_mut()),
+            None => read(&mut io::stdin().lock()),
+        }
+    }
 
-        Ok(())
+    fn print(&self, value: &Object) -> Result<(), RuntimeError> {
+        writeln!(self.output.borrow_mut(), "{}", stringify(value))
+            .map_err(|e| RuntimeError::Io(format!("Failed to write output: {}", e)))
     }
 
-    fn evaluate(&self, expr: &Expr) -> Result<Object, RuntimeError> {
+    pub(crate) fn evaluate(&self, expr: &Expr) -> Result<Object, RuntimeError> {
         match expr {
-            Expr::Literal(obj) => Ok(obj.clone()),
+            Expr::Literal(obj, _) => Ok(obj.clone()),
             Expr::Unary(op, right) => self.eval_unary(&op.0, right),
             Expr::Binary(left, op, right) => self.eval_binary(left, &op.0, right),
             Expr::Grouping(expr) => self.evaluate(expr),
-            Expr::Variable(name) => self.env.get(name),
+            Expr::Variable(name) => self.env.get(&name.1),
             Expr::Assign(name, value) => {
                 let value = self.evaluate(value)?;
-                self.env.assign(name, value.clone())?;
+                self.env.assign(&name.1, value.clone())?;
                 Ok(value)
             }
+            Expr::CompoundAssign(name, op, value) => {
+                let current = self.env.get(&name.1)?;
+                let value = binary(&current, &arithmetic(&op.0), &self.evaluate(value)?)?;
+                self.env.assign(&name.1, value.clone())?;
+                Ok(value)
+            }
+            Expr::Prefix(op, name) => self.eval_increment(name, op).map(|(_, new)| new),
+            Expr::Postfix(name, op) => self.eval_increment(name, op).map(|(old, _)| old),
             Expr::Logical(left, op, right) => self.eval_logical(left, &op.0, right),
+            Expr::Conditional(cond, then_expr, else_expr) => {
+                if self.truthy(&self.evaluate(cond)?) {
+                    self.evaluate(then_expr)
+                } else {
+                    self.evaluate(else_expr)
+                }
+            }
             Expr::Call(callee, paren, args) => self.eval_call(callee, paren, args),
+            Expr::Interpolation(parts) => {
+                let mut string = String::new();
+                for part in parts {
+                    string.push_str(&stringify(&self.evaluate(part)?));
+                }
+                Ok(Object::String(string))
+            }
+            Expr::Commented(expr, _) => self.evaluate(expr),
         }
     }
 
@@ -169,6 +353,13 @@ impl Interpreter {
         let Object::Callable(callable) = callee else {
             return Err(RuntimeError::TypeError("callable".to_string()));
         };
+        if arguments.len() != callable.arity() {
+            return Err(RuntimeError::TypeError(format!(
+                "Expected {} arguments but got {}.",
+                callable.arity(),
+                arguments.len()
+            )));
+        }
 
         callable.call(self, &arguments)
     }
@@ -190,6 +381,14 @@ impl Interpreter {
             _ => unreachable!(),
         }
     }
+    /// Applies `++` or `--` to a variable, returning its old and new values.
+    fn eval_increment(&self, name: &Token, op: &Token) -> Result<(Object, Object), RuntimeError> {
+        let old = self.env.get(&name.1)?;
+        let new = binary(&old, &arithmetic(&op.0), &Object::Integer(1))?;
+        self.env.assign(&name.1, new.clone())?;
+        Ok((old, new))
+    }
+
     fn eval_binary(
         &self,
         left: &Expr,
@@ -199,27 +398,28 @@ impl Interpreter {
         let left = self.evaluate(left)?;
         let right = self.evaluate(right)?;
 
-        match (&left, op, &right) {
-            (l, TokenType::EqualEqual, r) => Ok(Object::Boolean(eval_equal(l, r))),
-            (l, TokenType::BangEqual, r) => Ok(Object::Boolean(!eval_equal(l, r))),
-            (Object::Number(l), op, Object::Number(r)) => binary_number(l, op, r),
-            (Object::String(l), TokenType::Plus, Object::String(r)) => {
-                Ok(Object::String(format!("{}{}", l, r)))
-            }
-            _ => Err(RuntimeError::TypeError("Operand must be a number.".to_string())),
-        }
+        binary(&left, op, &right)
     }
 
     fn eval_unary(&self, op: &TokenType, right: &Expr) -> Result<Object, RuntimeError> {
         let right = self.evaluate(right)?;
         match (op, &right) {
             (TokenType::Minus, Object::Number(n)) => Ok(Object::Number(-n)),
+            // There's no integer -0, so negating zero gives the float one, as in jlox.
+            (TokenType::Minus, Object::Integer(0)) => Ok(Object::Number(-0.0)),
+            (TokenType::Minus, Object::Integer(n)) => n
+                .checked_neg()
+                .map(Object::Integer)
+                .ok_or(RuntimeError::Overflow),
+            (TokenType::Tilde, obj) => as_integer(obj)
+                .map(|n| Object::Integer(!n))
+                .ok_or_else(|| RuntimeError::IntegerOperand(*op, obj.type_name())),
             (TokenType::Bang, _) => Ok(Object::Boolean(!self.truthy(&right))),
-            _ => Err(RuntimeError::TypeError("Operand must be a number.".to_string())),
+            (op, obj) => Err(RuntimeError::NumberOperand(*op, obj.type_name())),
         }
     }
 
-    fn truthy(&self, obj: &Object) -> bool {
+    pub(crate) fn truthy(&self, obj: &Object) -> bool {
         match obj {
             Object::Nil => false,
             Object::Boolean(b) => *b,
@@ -231,25 +431,291 @@ impl Interpreter {
 fn eval_equal(left: &Object, right: &Object) -> bool {
     match (left, right) {
         (Object::Number(l), Object::Number(r)) => l == r,
+        (Object::Integer(l), Object::Integer(r)) => l == r,
+        // Compared as integers, since `as f64` rounds integers above 2^53.
+        (Object::Integer(l), Object::Number(r)) | (Object::Number(r), Object::Integer(l)) => {
+            r.fract() == 0.0 && *r >= i64::MIN as f64 && *r < i64::MAX as f64 && *r as i64 == *l
+        }
         (Object::String(l), Object::String(r)) => l == r,
         (Object::Boolean(l), Object::Boolean(r)) => l == r,
         (Object::Nil, Object::Nil) => true,
+        (Object::List(l), Object::List(r)) => {
+            l.len() == r.len() && l.iter().zip(r.iter()).all(|(l, r)| eval_equal(l, r))
+        }
         _ => false,
     }
 }
 
+fn binary(left: &Object, op: &TokenType, right: &Object) -> Result<Object, RuntimeError> {
+    match (left, op, right) {
+        (l, TokenType::EqualEqual, r) => Ok(Object::Boolean(eval_equal(l, r))),
+        (l, TokenType::BangEqual, r) => Ok(Object::Boolean(!eval_equal(l, r))),
+        (
+            l,
+            TokenType::Ampersand
+            | TokenType::Pipe
+            | TokenType::Caret
+            | TokenType::LessLess
+            | TokenType::GreaterGreater,
+            r,
+        ) => match (as_integer(l), as_integer(r)) {
+            (Some(l), Some(r)) => binary_integer(l, op, r),
+            _ => Err(RuntimeError::IntegerOperands(
+                *op,
+                l.type_name(),
+                r.type_name(),
+            )),
+        },
+        (Object::Integer(l), op, Object::Integer(r)) => binary_integer(*l, op, *r),
+        (Object::Number(_) | Object::Integer(_), op, Object::Number(_) | Object::Integer(_)) => {
+            binary_number(&as_float(left), op, &as_float(right))
+        }
+        (Object::String(l), TokenType::Plus, Object::String(r)) => {
+            Ok(Object::String(format!("{}{}", l, r)))
+        }
+        (l, TokenType::Plus, r) => Err(RuntimeError::AddOperands(l.type_name(), r.type_name())),
+        (l, op, r) => Err(RuntimeError::NumberOperands(
+            *op,
+            l.type_name(),
+            r.type_name(),
+        )),
+    }
+}
+
+/// Maps `+=`, `++` and friends to the arithmetic operator they apply.
+fn arithmetic(op: &TokenType) -> TokenType {
+    match op {
+        TokenType::PlusEqual | TokenType::PlusPlus => TokenType::Plus,
+        TokenType::MinusEqual | TokenType::MinusMinus => TokenType::Minus,
+        TokenType::StarEqual => TokenType::Star,
+        TokenType::SlashEqual => TokenType::Slash,
+        _ => unreachable!(),
+    }
+}
+
 fn binary_number(left: &f64, op: &TokenType, right: &f64) -> Result<Object, RuntimeError> {
     match op {
         TokenType::Plus => Ok(Object::Number(left + right)),
         TokenType::Minus => Ok(Object::Number(left - right)),
         TokenType::Star => Ok(Object::Number(left * right)),
         TokenType::Slash => Ok(Object::Number(left / right)),
+        TokenType::StarStar => Ok(Object::Number(left.powf(*right))),
+        TokenType::TildeSlash | TokenType::Percent if *right == 0.0 => {
+            Err(RuntimeError::DivisionByZero)
+        }
+        TokenType::TildeSlash => Ok(Object::Number((left / right).floor())),
+        TokenType::Percent => Ok(Object::Number(left - right * (left / right).floor())),
         TokenType::Greater => Ok(Object::Boolean(left > right)),
         TokenType::GreaterEqual => Ok(Object::Boolean(left >= right)),
         TokenType::Less => Ok(Object::Boolean(left < right)),
         TokenType::LessEqual => Ok(Object::Boolean(left <= right)),
         TokenType::EqualEqual => Ok(Object::Boolean(left == right)),
 
-        _ => Err(RuntimeError::TypeError("Operand must be a number.".to_string())),
+        _ => unreachable!(),
+    }
+}
+
+/// Integer arithmetic is checked; `/` and operations on mixed operands
+/// produce floats instead.
+fn binary_integer(left: i64, op: &TokenType, right: i64) -> Result<Object, RuntimeError> {
+    let value = match op {
+        TokenType::Plus => left.checked_add(right),
+        TokenType::Minus => left.checked_sub(right),
+        TokenType::Star if (left == 0 || right == 0) && (left < 0) != (right < 0) => {
+            return Ok(Object::Number(-0.0))
+        }
+        TokenType::Star => left.checked_mul(right),
+        TokenType::TildeSlash | TokenType::Percent if right == 0 => {
+            return Err(RuntimeError::DivisionByZero)
+        }
+        // Both round towards negative infinity, so `a == (a ~/ b) * b + a % b`.
+        TokenType::TildeSlash => left.checked_div(right).map(|quotient| {
+            if left % right != 0 && (left < 0) != (right < 0) {
+                quotient - 1
+            } else {
+                quotient
+            }
+        }),
+        TokenType::Percent => left.checked_rem(right).map(|rem| {
+            if rem != 0 && (rem < 0) != (right < 0) {
+                rem + right
+            } else {
+                rem
+            }
+        }),
+        TokenType::StarStar if right >= 0 => u32::try_from(right)
+            .ok()
+            .and_then(|exponent| left.checked_pow(exponent)),
+        TokenType::Ampersand => Some(left & right),
+        TokenType::Pipe => Some(left | right),
+        TokenType::Caret => Some(left ^ right),
+        TokenType::LessLess | TokenType::GreaterGreater if !(0..64).contains(&right) => {
+            return Err(RuntimeError::ShiftAmount(right))
+        }
+        // Shifting bits out of the top is an overflow.
+        TokenType::LessLess => Some(left << right).filter(|shifted| shifted >> right == left),
+        TokenType::GreaterGreater => Some(left >> right),
+        TokenType::Greater => return Ok(Object::Boolean(left > right)),
+        TokenType::GreaterEqual => return Ok(Object::Boolean(left >= right)),
+        TokenType::Less => return Ok(Object::Boolean(left < right)),
+        TokenType::LessEqual => return Ok(Object::Boolean(left <= right)),
+        _ => return binary_number(&(left as f64), op, &(right as f64)),
+    };
+
+    value.map(Object::Integer).ok_or(RuntimeError::Overflow)
+}
+
+/// Bitwise operands may be integers or floats with no fractional part.
+fn as_integer(obj: &Object) -> Option<i64> {
+    match obj {
+        Object::Integer(n) => Some(*n),
+        Object::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => Some(*n as i64),
+        _ => None,
+    }
+}
+
+fn as_float(obj: &Object) -> f64 {
+    match obj {
+        Object::Number(n) => *n,
+        Object::Integer(n) => *n as f64,
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RuntimeError;
+    use crate::testing;
+
+    fn evaluate(source: &str) -> RuntimeError {
+        match testing::evaluate(source) {
+            Ok(_) => panic!("{} should fail", source),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn negating_a_non_number() {
+        let e = evaluate("-\"a\"");
+        assert_eq!(e.to_string(), "Operand must be a number.");
+        assert!(matches!(e, RuntimeError::NumberOperand(_, "string")));
+    }
+
+    #[test]
+    fn adding_mixed_operands() {
+        let e = evaluate("\"a\" + 1");
+        assert_eq!(
+            e.to_string(),
+            "Operands must be two numbers or two strings."
+        );
+        assert!(matches!(e, RuntimeError::AddOperands("string", "integer")));
+    }
+
+    #[test]
+    fn comparing_non_numbers() {
+        let e = evaluate("true < 2");
+        assert_eq!(e.to_string(), "Operands must be numbers.");
+        assert!(matches!(
+            e,
+            RuntimeError::NumberOperands(_, "bool", "integer")
+        ));
+    }
+
+    #[test]
+    fn bitwise_on_a_fraction() {
+        let e = evaluate("1.5 & 1");
+        assert_eq!(e.to_string(), "Operands must be integers.");
+        assert!(matches!(
+            e,
+            RuntimeError::IntegerOperands(_, "number", "integer")
+        ));
+    }
+
+    #[test]
+    fn complementing_a_non_integer() {
+        let e = evaluate("~\"x\"");
+        assert_eq!(e.to_string(), "Operand must be an integer.");
+        assert!(matches!(e, RuntimeError::IntegerOperand(_, "string")));
+    }
+
+    #[test]
+    fn interpolating_values() {
+        let source = r#"
+            var a = 1;
+            var s = "x";
+            print "${a}";
+            print "a${a + 1}b${s}c";
+            print "${"}" + "${s}"}!";
+            print "${nil} ${true} ${1.5} ${"\${a}"}";
+        "#;
+        assert_eq!(testing::run(source), "1\na2bxc\n}x!\nnil true 1.5 ${a}\n");
+    }
+
+    #[test]
+    fn shifting_out_of_range() {
+        for source in ["1 >> 64", "1 << 64", "1 << -1"] {
+            let e = evaluate(source);
+            assert!(matches!(e, RuntimeError::ShiftAmount(_)), "{}", source);
+        }
+        assert_eq!(
+            evaluate("1 >> 64").to_string(),
+            "Shift amount 64 is out of range."
+        );
+        assert!(matches!(evaluate("1 << 63"), RuntimeError::Overflow));
+        assert_eq!(
+            testing::run("print 1 << 20; print -8 >> 1;"),
+            "1048576\n-4\n"
+        );
+    }
+
+    #[test]
+    fn comparing_integers_with_floats_exactly() {
+        let source = r#"
+            print 9007199254740993 == 9007199254740992.0;
+            print 9007199254740992 == 9007199254740992.0;
+            print 1 == 1.0;
+            print 1 != 1.5;
+            print 9223372036854775807 == 9223372036854775808.0;
+        "#;
+        assert_eq!(testing::run(source), "false\ntrue\ntrue\ntrue\nfalse\n");
+    }
+
+    #[test]
+    fn compound_assignment_and_increments() {
+        let source = r#"
+            var a = 1;
+            a += 2; print a;
+            a -= 1; print a;
+            a *= 3; print a;
+            a /= 4; print a;
+            var b = 5;
+            print b++; print b;
+            print ++b; print b--; print --b;
+            var s = "x";
+            s += "y"; print s;
+        "#;
+        assert_eq!(testing::run(source), "3\n2\n6\n1.5\n5\n6\n7\n7\n5\nxy\n");
+
+        assert_eq!(
+            testing::run("var n; n += 1;"),
+            "error: Operands must be two numbers or two strings.\n"
+        );
+        assert_eq!(testing::run("m++;"), "error: Undefined variable 'm'.\n");
+        assert_eq!(
+            testing::run("var c = 9223372036854775807; c++;"),
+            "error: Integer overflow.\n"
+        );
+    }
+
+    #[test]
+    fn conditional_runs_one_branch() {
+        let source = r#"
+            fun say(s) { print s; return s; }
+            print true ? 1 : 2;
+            print nil ? 1 : false ? 2 : 3;
+            print 0 ? say("then") : say("else");
+            print false ? say("then") : say("else");
+        "#;
+        assert_eq!(testing::run(source), "1\n3\nthen\nthen\nelse\nelse\n");
     }
 }
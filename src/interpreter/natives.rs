@@ -0,0 +1,688 @@
+use std::{
+    cell::Cell,
+    fmt::Display,
+    fs,
+    io::{self, Write},
+    path::Path,
+    rc::Rc,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{stringify, Interpreter, Object, RuntimeError};
+
+/// A group of native functions that reach outside the interpreter. A
+/// script can only call natives whose capability it was granted.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Capability {
+    Time,
+    Fs,
+    Process,
+    Env,
+    Random,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 5] = [
+        Capability::Time,
+        Capability::Fs,
+        Capability::Process,
+        Capability::Env,
+        Capability::Random,
+    ];
+
+    /// Granted by `Interpreter::new`; neither can affect the host.
+    pub const DEFAULT: [Capability; 2] = [Capability::Time, Capability::Random];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::Time => "time",
+            Capability::Fs => "fs",
+            Capability::Process => "process",
+            Capability::Env => "env",
+            Capability::Random => "random",
+        }
+    }
+}
+
+impl FromStr for Capability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Capability::ALL
+            .into_iter()
+            .find(|capability| capability.name() == s)
+            .ok_or_else(|| format!("Unknown capability: {}", s))
+    }
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+pub(super) struct Native {
+    pub name: &'static str,
+    pub arity: usize,
+    /// `None` for natives every script may call.
+    pub capability: Option<Capability>,
+    pub func: fn(&Interpreter, &[Object]) -> Result<Object, RuntimeError>,
+}
+
+/// Every native function, defined as a global by `Interpreter::new`.
+pub(super) const NATIVES: &[Native] = &[
+    Native {
+        name: "clock",
+        arity: 0,
+        capability: Some(Capability::Time),
+        func: clock,
+    },
+    Native {
+        name: "random",
+        arity: 0,
+        capability: Some(Capability::Random),
+        func: random,
+    },
+    Native {
+        name: "len",
+        arity: 1,
+        capability: None,
+        func: len,
+    },
+    Native {
+        name: "get",
+        arity: 2,
+        capability: None,
+        func: get,
+    },
+    Native {
+        name: "type",
+        arity: 1,
+        capability: None,
+        func: type_of,
+    },
+    Native {
+        name: "str",
+        arity: 1,
+        capability: None,
+        func: to_str,
+    },
+    Native {
+        name: "num",
+        arity: 1,
+        capability: None,
+        func: num,
+    },
+    Native {
+        name: "arity",
+        arity: 1,
+        capability: None,
+        func: arity,
+    },
+    Native {
+        name: "readFile",
+        arity: 1,
+        capability: Some(Capability::Fs),
+        func: read_file,
+    },
+    Native {
+        name: "readLines",
+        arity: 1,
+        capability: Some(Capability::Fs),
+        func: read_lines,
+    },
+    Native {
+        name: "writeFile",
+        arity: 2,
+        capability: Some(Capability::Fs),
+        func: write_file,
+    },
+    Native {
+        name: "appendFile",
+        arity: 2,
+        capability: Some(Capability::Fs),
+        func: append_file,
+    },
+    Native {
+        name: "fileExists",
+        arity: 1,
+        capability: Some(Capability::Fs),
+        func: file_exists,
+    },
+    // Stdin and the arguments are the script's own input, so filters can
+    // run without any capability.
+    Native {
+        name: "readLine",
+        arity: 0,
+        capability: None,
+        func: read_line,
+    },
+    Native {
+        name: "readAll",
+        arity: 0,
+        capability: None,
+        func: read_all,
+    },
+    Native {
+        name: "args",
+        arity: 0,
+        capability: None,
+        func: args,
+    },
+    Native {
+        name: "exit",
+        arity: 1,
+        capability: Some(Capability::Process),
+        func: exit,
+    },
+    Native {
+        name: "getenv",
+        arity: 1,
+        capability: Some(Capability::Env),
+        func: getenv,
+    },
+    Native {
+        name: "setenv",
+        arity: 2,
+        capability: Some(Capability::Env),
+        func: setenv,
+    },
+    Native {
+        name: "env",
+        arity: 0,
+        capability: Some(Capability::Env),
+        func: env,
+    },
+];
+
+fn clock(_interpreter: &Interpreter, _arguments: &[Object]) -> Result<Object, RuntimeError> {
+    Ok(Object::Number(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as f64,
+    ))
+}
+
+thread_local! {
+    static SEED: Cell<u64> = Cell::new(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64)
+            | 1,
+    );
+}
+
+/// A number in `[0, 1)` from an xorshift generator seeded by the clock.
+fn random(_interpreter: &Interpreter, _arguments: &[Object]) -> Result<Object, RuntimeError> {
+    let mut x = SEED.get();
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    SEED.set(x);
+
+    Ok(Object::Number((x >> 11) as f64 / (1u64 << 53) as f64))
+}
+
+/// The length of a string in characters, or of a list.
+fn len(_interpreter: &Interpreter, arguments: &[Object]) -> Result<Object, RuntimeError> {
+    let len = match &arguments[0] {
+        Object::String(s) => s.chars().count(),
+        Object::List(items) => items.len(),
+        _ => return Err(RuntimeError::ArgumentType("len", "a string or a list")),
+    };
+    Ok(Object::Integer(len as i64))
+}
+
+fn get(_interpreter: &Interpreter, arguments: &[Object]) -> Result<Object, RuntimeError> {
+    let (Object::List(items), Object::Integer(index)) = (&arguments[0], &arguments[1]) else {
+        return Err(RuntimeError::ArgumentType("get", "a list and an integer"));
+    };
+    usize::try_from(*index)
+        .ok()
+        .and_then(|i| items.get(i))
+        .cloned()
+        .ok_or(RuntimeError::IndexOutOfRange(*index))
+}
+
+fn type_of(_interpreter: &Interpreter, arguments: &[Object]) -> Result<Object, RuntimeError> {
+    Ok(Object::String(arguments[0].type_name().to_string()))
+}
+
+/// A value the way `print` shows it.
+fn to_str(_interpreter: &Interpreter, arguments: &[Object]) -> Result<Object, RuntimeError> {
+    Ok(Object::String(stringify(&arguments[0])))
+}
+
+/// A string parsed as an integer or number, or nil if it isn't one.
+/// Numbers are returned as they are.
+fn num(_interpreter: &Interpreter, arguments: &[Object]) -> Result<Object, RuntimeError> {
+    let s = match &arguments[0] {
+        Object::Number(_) | Object::Integer(_) => return Ok(arguments[0].clone()),
+        Object::String(s) => s.trim(),
+        _ => return Ok(Object::Nil),
+    };
+    // Rust also accepts "inf" and "NaN", which aren't Lox numbers.
+    if s.contains(|c: char| c.is_ascii_alphabetic() && c != 'e' && c != 'E') {
+        return Ok(Object::Nil);
+    }
+
+    if let Ok(n) = s.parse() {
+        return Ok(Object::Integer(n));
+    }
+    // Out-of-range floats such as "1e400" parse to infinity.
+    Ok(s.parse()
+        .ok()
+        .filter(|n: &f64| n.is_finite())
+        .map_or(Object::Nil, Object::Number))
+}
+
+fn arity(_interpreter: &Interpreter, arguments: &[Object]) -> Result<Object, RuntimeError> {
+    let Object::Callable(callable) = &arguments[0] else {
+        return Err(RuntimeError::ArgumentType("arity", "a function"));
+    };
+    Ok(Object::Integer(callable.arity() as i64))
+}
+
+fn read_file(_interpreter: &Interpreter, arguments: &[Object]) -> Result<Object, RuntimeError> {
+    let [path] = strings("readFile", arguments)?;
+    fs::read_to_string(path)
+        .map(Object::String)
+        .map_err(|e| io_error("read", path, e))
+}
+
+fn read_lines(_interpreter: &Interpreter, arguments: &[Object]) -> Result<Object, RuntimeError> {
+    let [path] = strings("readLines", arguments)?;
+    let contents = fs::read_to_string(path).map_err(|e| io_error("read", path, e))?;
+    let lines = contents
+        .lines()
+        .map(|line| Object::String(line.to_string()))
+        .collect();
+    Ok(Object::List(Rc::new(lines)))
+}
+
+fn write_file(_interpreter: &Interpreter, arguments: &[Object]) -> Result<Object, RuntimeError> {
+    let [path, contents] = strings("writeFile", arguments)?;
+    fs::write(path, contents).map_err(|e| io_error("write", path, e))?;
+    Ok(Object::Nil)
+}
+
+fn append_file(_interpreter: &Interpreter, arguments: &[Object]) -> Result<Object, RuntimeError> {
+    let [path, contents] = strings("appendFile", arguments)?;
+    fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|e| io_error("write", path, e))?;
+    Ok(Object::Nil)
+}
+
+fn file_exists(_interpreter: &Interpreter, arguments: &[Object]) -> Result<Object, RuntimeError> {
+    let [path] = strings("fileExists", arguments)?;
+    Ok(Object::Boolean(Path::new(path).exists()))
+}
+
+/// The next line of stdin without its line ending, or nil at the end.
+fn read_line(interpreter: &Interpreter, _arguments: &[Object]) -> Result<Object, RuntimeError> {
+    let mut line = String::new();
+    let read = interpreter
+        .read_input(|input| input.read_line(&mut line))
+        .map_err(stdin_error)?;
+    if read == 0 {
+        return Ok(Object::Nil);
+    }
+
+    let line = line.strip_suffix('\n').unwrap_or(&line);
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    Ok(Object::String(line.to_string()))
+}
+
+/// The rest of stdin.
+fn read_all(interpreter: &Interpreter, _arguments: &[Object]) -> Result<Object, RuntimeError> {
+    let mut all = String::new();
+    interpreter
+        .read_input(|input| input.read_to_string(&mut all))
+        .map_err(stdin_error)?;
+    Ok(Object::String(all))
+}
+
+fn args(interpreter: &Interpreter, _arguments: &[Object]) -> Result<Object, RuntimeError> {
+    let args = interpreter
+        .args
+        .iter()
+        .map(|arg| Object::String(arg.clone()))
+        .collect();
+    Ok(Object::List(Rc::new(args)))
+}
+
+/// Stops the program with the given exit code, however deep the call.
+fn exit(_interpreter: &Interpreter, arguments: &[Object]) -> Result<Object, RuntimeError> {
+    match &arguments[0] {
+        Object::Integer(code) => match i32::try_from(*code) {
+            Ok(code) => Err(RuntimeError::Exit(code)),
+            Err(_) => Err(RuntimeError::Overflow),
+        },
+        _ => Err(RuntimeError::ArgumentType("exit", "an integer")),
+    }
+}
+
+/// The value of an environment variable, or nil if it isn't set.
+fn getenv(_interpreter: &Interpreter, arguments: &[Object]) -> Result<Object, RuntimeError> {
+    let [name] = strings("getenv", arguments)?;
+    Ok(std::env::var(name).map_or(Object::Nil, Object::String))
+}
+
+fn setenv(_interpreter: &Interpreter, arguments: &[Object]) -> Result<Object, RuntimeError> {
+    let [name, value] = strings("setenv", arguments)?;
+    if name.is_empty() || name.contains(['=', '\0']) || value.contains('\0') {
+        return Err(RuntimeError::ArgumentType(
+            "setenv",
+            "a variable name and a value",
+        ));
+    }
+    std::env::set_var(name, value);
+    Ok(Object::Nil)
+}
+
+/// Every environment variable as a `NAME=value` string, sorted by name.
+fn env(_interpreter: &Interpreter, _arguments: &[Object]) -> Result<Object, RuntimeError> {
+    let mut vars: Vec<_> = std::env::vars_os()
+        .map(|(name, value)| format!("{}={}", name.to_string_lossy(), value.to_string_lossy()))
+        .collect();
+    vars.sort();
+    let vars = vars.into_iter().map(Object::String).collect();
+    Ok(Object::List(Rc::new(vars)))
+}
+
+/// The arguments of a native that takes only strings.
+fn strings<'a, const N: usize>(
+    name: &'static str,
+    arguments: &'a [Object],
+) -> Result<[&'a str; N], RuntimeError> {
+    let mut strings = [""; N];
+    for (string, argument) in strings.iter_mut().zip(arguments) {
+        let Object::String(argument) = argument else {
+            return Err(RuntimeError::ArgumentType(name, "strings"));
+        };
+        *string = argument;
+    }
+    Ok(strings)
+}
+
+fn io_error(action: &str, path: &str, e: io::Error) -> RuntimeError {
+    RuntimeError::Io(format!("Failed to {} file {}: {}", action, path, e))
+}
+
+fn stdin_error(e: io::Error) -> RuntimeError {
+    RuntimeError::Io(format!("Failed to read standard input: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Capability, NATIVES};
+    use crate::{
+        interpreter::{Interpreter, RuntimeError},
+        testing::{parse, run_with},
+    };
+
+    fn run(capabilities: &[Capability], source: &str) -> String {
+        run_with(Interpreter::with_capabilities(capabilities), &parse(source))
+    }
+
+    #[test]
+    fn denies_natives_without_their_capability() {
+        for native in NATIVES {
+            let Some(capability) = native.capability else {
+                continue;
+            };
+            let arguments = vec!["\"x\""; native.arity].join(", ");
+            let source = format!("{}({});", native.name, arguments);
+            assert_eq!(
+                run(&[], &source),
+                format!(
+                    "error: '{}' needs the {} capability.\n",
+                    native.name, capability
+                ),
+            );
+        }
+    }
+
+    #[test]
+    fn grants_capabilities() {
+        assert_eq!(run(&[Capability::Time], "print clock() > 0;"), "true\n");
+        assert_eq!(
+            run(&Capability::DEFAULT, "fileExists(\"x\");"),
+            "error: 'fileExists' needs the fs capability.\n"
+        );
+        assert_eq!(
+            run(&[Capability::Env], "print getenv(\"PATH\") != nil;"),
+            "true\n"
+        );
+    }
+
+    #[test]
+    fn denied_natives_are_still_values() {
+        let source = "print type(exit); print arity(writeFile); print str(readFile);";
+        assert_eq!(run(&[], source), "function\n2\n<fn readFile>\n");
+    }
+
+    #[test]
+    fn parses_capabilities() {
+        for capability in Capability::ALL {
+            assert_eq!(capability.name().parse::<Capability>(), Ok(capability));
+        }
+        assert_eq!(
+            "net".parse::<Capability>(),
+            Err("Unknown capability: net".to_string())
+        );
+    }
+
+    #[test]
+    fn reads_and_writes_files() {
+        let dir = std::env::temp_dir().join(format!("lox-natives-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notes.txt");
+        let source = format!(
+            r#"
+            var path = "{}";
+            print fileExists(path);
+            writeFile(path, "one\n");
+            appendFile(path, "two\r\nthree");
+            print fileExists(path);
+            print readFile(path) == "one\ntwo\r\nthree";
+            var lines = readLines(path);
+            print len(lines);
+            print get(lines, 1);
+            "#,
+            path.display()
+        );
+        let output = run(&[Capability::Fs], &source);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(output, "false\ntrue\ntrue\n3\ntwo\n");
+    }
+
+    #[test]
+    fn reports_file_errors() {
+        let output = run(&[Capability::Fs], "readFile(\"/nonexistent/lox\");");
+        assert!(
+            output.starts_with("error: Failed to read file /nonexistent/lox: "),
+            "{}",
+            output
+        );
+        assert_eq!(
+            run(&[Capability::Fs], "writeFile(\"x\", 1);"),
+            "error: Arguments to 'writeFile' must be strings.\n"
+        );
+    }
+
+    #[test]
+    fn reads_stdin() {
+        let interpreter =
+            Interpreter::with_capabilities(&[]).with_input("one\ntwo\r\nthree\nfour".as_bytes());
+        let source = "print readLine(); print readLine(); print readLine(); print readAll();";
+        assert_eq!(
+            run_with(interpreter, &parse(source)),
+            "one\ntwo\nthree\nfour\n"
+        );
+
+        let interpreter = Interpreter::with_capabilities(&[]).with_input("last\n".as_bytes());
+        let source = "print readLine(); print readLine(); print readAll() == \"\";";
+        assert_eq!(run_with(interpreter, &parse(source)), "last\nnil\ntrue\n");
+    }
+
+    #[test]
+    fn returns_args() {
+        let args = ["first".to_string(), "second arg".to_string()];
+        let interpreter = Interpreter::with_capabilities(&[]).with_args(&args);
+        let source = "var a = args(); print len(a); print get(a, 1);";
+        assert_eq!(run_with(interpreter, &parse(source)), "2\nsecond arg\n");
+        assert_eq!(run(&[], "print len(args());"), "0\n");
+    }
+
+    #[test]
+    fn exits_with_a_code() {
+        let stmts = parse("fun quit() { exit(3); } quit(); print 1;");
+        let result = Interpreter::with_capabilities(&[Capability::Process]).interpret(&stmts);
+        assert!(matches!(result, Err(RuntimeError::Exit(3))));
+
+        let process = [Capability::Process];
+        assert_eq!(
+            run(&process, "print 1; exit(0); print 2;"),
+            "1\nerror: Exited with code 0.\n"
+        );
+        assert_eq!(
+            run(&process, "exit(1.5);"),
+            "error: Arguments to 'exit' must be an integer.\n"
+        );
+        assert_eq!(
+            run(&process, "exit(\"1\");"),
+            "error: Arguments to 'exit' must be an integer.\n"
+        );
+        assert_eq!(
+            run(&process, "exit(4294967296);"),
+            "error: Integer overflow.\n"
+        );
+    }
+
+    #[test]
+    fn reads_and_writes_the_environment() {
+        let name = format!("LOX_NATIVES_{}", std::process::id());
+        let source = format!(
+            r#"
+            var name = "{}";
+            print getenv(name);
+            setenv(name, "a value");
+            print getenv(name);
+            var vars = env();
+            for (var i = 0; i < len(vars); i = i + 1) {{
+              if (get(vars, i) == name + "=a value") print "listed";
+            }}
+            "#,
+            name
+        );
+        let output = run(&[Capability::Env], &source);
+        std::env::remove_var(&name);
+        assert_eq!(output, "nil\na value\nlisted\n");
+    }
+
+    #[test]
+    fn rejects_bad_environment_variables() {
+        for arguments in [
+            "\"\", \"x\"",
+            "\"A=B\", \"x\"",
+            "\"A\\u{0}\", \"x\"",
+            "\"A\", \"x\\u{0}\"",
+        ] {
+            assert_eq!(
+                run(&[Capability::Env], &format!("setenv({});", arguments)),
+                "error: Arguments to 'setenv' must be a variable name and a value.\n",
+                "{}",
+                arguments
+            );
+        }
+        assert_eq!(
+            run(&[Capability::Env], "getenv(1);"),
+            "error: Arguments to 'getenv' must be strings.\n"
+        );
+    }
+
+    /// The lines a program prints.
+    fn values(source: &str) -> Vec<String> {
+        run(&[], source).lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn names_types() {
+        let source = "fun f() {} print type(1); print type(1.5); print type(\"s\"); \
+            print type(true); print type(nil); print type(f); print type(clock); \
+            print type(args());";
+        assert_eq!(
+            values(source),
+            ["integer", "number", "string", "bool", "nil", "function", "function", "list"]
+        );
+    }
+
+    #[test]
+    fn converts_to_strings_like_print() {
+        let source = "fun f() {} print str(1); print str(1.5); print str(2.0); \
+            print str(123456789012); print str(0 * -1); print str(1e21); print str(nil); \
+            print str(false); print str(f); print str(clock); print type(str(1));";
+        assert_eq!(
+            values(source),
+            [
+                "1",
+                "1.5",
+                "2",
+                "1.23456789012E11",
+                "-0",
+                "1.0E21",
+                "nil",
+                "false",
+                "<fn f>",
+                "<fn clock>",
+                "string"
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_numbers() {
+        let source = "print num(\"42\"); print type(num(\"42\")); print num(\" 1.5 \"); \
+            print type(num(\"1.5\")); print num(\"-3e2\"); print num(7); print num(2.5);";
+        assert_eq!(
+            values(source),
+            ["42", "integer", "1.5", "number", "-300", "7", "2.5"]
+        );
+
+        for argument in [
+            "\"1e400\"",
+            "\"-1e400\"",
+            "\"inf\"",
+            "\"NaN\"",
+            "\"x\"",
+            "\"\"",
+            "true",
+            "nil",
+        ] {
+            assert_eq!(
+                run(&[], &format!("print num({});", argument)),
+                "nil\n",
+                "{}",
+                argument
+            );
+        }
+    }
+
+    #[test]
+    fn counts_parameters() {
+        let source = "fun f(a, b) {} fun g() {} print arity(f); print arity(g); \
+            print arity(clock); print arity(get);";
+        assert_eq!(values(source), ["2", "0", "0", "2"]);
+        assert_eq!(
+            run(&[], "arity(1);"),
+            "error: Arguments to 'arity' must be a function.\n"
+        );
+    }
+}
@@ -1,30 +1,55 @@
 use std::{fmt::Display, rc::Rc};
 
-use super::callable::LoxCallable;
+use super::{
+    callable::LoxCallable,
+    stringify::{integer_literal, number_literal},
+};
 
 #[derive(Clone)]
 pub enum Object {
     String(String),
     Number(f64),
+    Integer(i64),
     Boolean(bool),
     Nil,
     Callable(Rc<dyn LoxCallable>),
+    /// Only built by natives; the language has no list syntax.
+    List(Rc<Vec<Object>>),
+}
+
+impl Object {
+    /// The name runtime errors and the `type` native use for this kind of
+    /// value.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::String(_) => "string",
+            Object::Number(_) => "number",
+            Object::Integer(_) => "integer",
+            Object::Boolean(_) => "bool",
+            Object::Nil => "nil",
+            Object::Callable(_) => "function",
+            Object::List(_) => "list",
+        }
+    }
 }
 
 impl Display for Object {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let output = match self {
             Object::String(s) => s.to_string(),
-            Object::Number(n) => {
-                if n.fract() == 0.0 {
-                    format!("{:.1}", n)
-                } else {
-                    format!("{}", n)
-                }
-            }
+            Object::Number(n) => number_literal(*n),
+            Object::Integer(n) => integer_literal(*n),
             Object::Boolean(b) => b.to_string(),
             Object::Nil => "nil".to_string(),
             Object::Callable(callable) => callable.to_string(),
+            Object::List(items) => format!(
+                "[{}]",
+                items
+                    .iter()
+                    .map(Object::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
         };
 
         write!(f, "{}", output)
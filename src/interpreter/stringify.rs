@@ -0,0 +1,164 @@
+//! Value formatting shared by `print`, the `evaluate` command and token
+//! literals, following jlox: literals are printed the way Java's
+//! `Double.toString` would, and `print` drops a trailing `.0`.
+//!
+//! Division follows the same rules for every numeric type: `/` is always
+//! floating point, so dividing by zero gives `Infinity` or `NaN`, while
+//! `~/` and `%` report a runtime error.
+
+use super::Object;
+
+/// The form `print` and `evaluate` show to the user.
+pub fn stringify(obj: &Object) -> String {
+    match obj {
+        Object::Number(n) => whole(number_literal(*n)),
+        Object::Integer(n) => whole(integer_literal(*n)),
+        Object::List(items) => format!(
+            "[{}]",
+            items.iter().map(stringify).collect::<Vec<_>>().join(", ")
+        ),
+        _ => obj.to_string(),
+    }
+}
+
+fn whole(literal: String) -> String {
+    match literal.strip_suffix(".0") {
+        Some(whole) => whole.to_string(),
+        None => literal,
+    }
+}
+
+/// A float the way Java's `Double.toString` prints it: plain notation
+/// with at least one fractional digit between 10^-3 and 10^7, and
+/// scientific notation like `1.0E21` outside that range.
+pub fn number_literal(n: f64) -> String {
+    if n.is_nan() {
+        return "NaN".to_string();
+    }
+    if n.is_infinite() {
+        let sign = if n < 0.0 { "-" } else { "" };
+        return format!("{}Infinity", sign);
+    }
+
+    // Rust's `{:e}` yields the shortest digits that round-trip.
+    let scientific = format!("{:e}", n.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let digits = mantissa.replace('.', "");
+    let exponent: i32 = exponent.parse().unwrap();
+
+    let sign = if n.is_sign_negative() { "-" } else { "" };
+    format!("{}{}", sign, layout(&digits, exponent))
+}
+
+/// Integers use the same layout as floats so both kinds of number look
+/// alike in token literals, but every digit is kept.
+pub fn integer_literal(n: i64) -> String {
+    let digits = n.unsigned_abs().to_string();
+    let exponent = digits.len() as i32 - 1;
+    let significant = digits.trim_end_matches('0');
+    let digits = if significant.is_empty() {
+        "0"
+    } else {
+        significant
+    };
+
+    let sign = if n < 0 { "-" } else { "" };
+    format!("{}{}", sign, layout(digits, exponent))
+}
+
+/// Places the decimal point in `digits`, whose first digit is in the
+/// `10^exponent` position.
+fn layout(digits: &str, exponent: i32) -> String {
+    if !(-3..7).contains(&exponent) {
+        let fraction = if digits.len() > 1 { &digits[1..] } else { "0" };
+        return format!("{}.{}E{}", &digits[..1], fraction, exponent);
+    }
+
+    if exponent < 0 {
+        let zeros = "0".repeat((-exponent - 1) as usize);
+        return format!("0.{}{}", zeros, digits);
+    }
+
+    let point = exponent as usize + 1;
+    if digits.len() > point {
+        format!("{}.{}", &digits[..point], &digits[point..])
+    } else {
+        format!("{}{}.0", digits, "0".repeat(point - digits.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{integer_literal, number_literal, stringify};
+    use crate::testing;
+
+    fn evaluate(source: &str) -> Result<String, String> {
+        testing::evaluate(source)
+            .map(|value| stringify(&value))
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn number_literals() {
+        assert_eq!(number_literal(0.0), "0.0");
+        assert_eq!(number_literal(-0.0), "-0.0");
+        assert_eq!(number_literal(f64::NAN), "NaN");
+        assert_eq!(number_literal(f64::INFINITY), "Infinity");
+        assert_eq!(number_literal(f64::NEG_INFINITY), "-Infinity");
+        assert_eq!(number_literal(1e21), "1.0E21");
+        assert_eq!(number_literal(1e-4), "1.0E-4");
+        assert_eq!(number_literal(1e7), "1.0E7");
+        assert_eq!(number_literal(9999999.0), "9999999.0");
+        assert_eq!(number_literal(0.001), "0.001");
+        assert_eq!(number_literal(100.0), "100.0");
+        assert_eq!(number_literal(1.5), "1.5");
+        assert_eq!(number_literal(123456789.0), "1.23456789E8");
+    }
+
+    #[test]
+    fn integer_literals() {
+        assert_eq!(integer_literal(0), "0.0");
+        assert_eq!(integer_literal(100), "100.0");
+        assert_eq!(integer_literal(-7), "-7.0");
+        assert_eq!(integer_literal(10_000_000), "1.0E7");
+        assert_eq!(integer_literal(i64::MAX), "9.223372036854775807E18");
+    }
+
+    #[test]
+    fn print_drops_trailing_zero() {
+        let printed = [
+            ("-0", "-0"),
+            ("0 * -1", "-0"),
+            ("-1 * 0", "-0"),
+            ("-0.0", "-0"),
+            ("100", "100"),
+            ("100.0", "100"),
+            ("1e21", "1.0E21"),
+            ("0.0001", "1.0E-4"),
+            ("0.001", "0.001"),
+            ("0 / 0", "NaN"),
+            ("nil", "nil"),
+            ("123456789012", "1.23456789012E11"),
+            ("-10000000", "-1.0E7"),
+            ("9223372036854775807", "9.223372036854775807E18"),
+        ];
+        for (source, output) in printed {
+            let program = format!("print {};", source);
+            assert_eq!(
+                testing::run(&program),
+                format!("{}\n", output),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert_eq!(evaluate("1 / 0"), Ok("Infinity".to_string()));
+        assert_eq!(evaluate("-1 / 0"), Ok("-Infinity".to_string()));
+        assert_eq!(evaluate("0 / 0"), Ok("NaN".to_string()));
+        assert_eq!(evaluate("1 ~/ 0"), Err("Division by zero.".to_string()));
+        assert_eq!(evaluate("1 % 0"), Err("Division by zero.".to_string()));
+    }
+}
@@ -0,0 +1,24 @@
+use super::{Interpreter, RuntimeError};
+use crate::parse::Statement;
+
+/// Observes a running program. Tools such as the debugger hook into the
+/// interpreter through this; every method defaults to doing nothing.
+pub trait Tracer {
+    /// Called before each statement runs, except blocks and comments.
+    fn statement(&self, _interpreter: &Interpreter, _stmt: &Statement) -> Result<(), RuntimeError> {
+        Ok(())
+    }
+
+    /// Called once a statement passed to `statement` has finished.
+    fn statement_end(&self, _stmt: &Statement) {}
+
+    /// Called when an `if` picks a branch, and each time a loop checks
+    /// whether to run its body again.
+    fn branch(&self, _stmt: &Statement, _taken: bool) {}
+
+    /// Called when a Lox function declared on `line` is entered.
+    fn call(&self, _name: &str, _line: usize) {}
+
+    /// Called when the innermost Lox function returns, normally or not.
+    fn ret(&self) {}
+}
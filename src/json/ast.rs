@@ -0,0 +1,471 @@
+use crate::{
+    interpreter::Object,
+    lex::{Token, TokenType},
+    parse::{Expr, Statement},
+};
+
+use super::{Json, JsonError};
+
+pub fn to_json(stmts: &[Statement]) -> Json {
+    Json::Array(stmts.iter().map(statement).collect())
+}
+
+pub fn from_json(json: &Json) -> Result<Vec<Statement>, JsonError> {
+    json.as_array()?.iter().map(to_statement).collect()
+}
+
+fn node(kind: &str, fields: Vec<(&str, Json)>) -> Json {
+    let mut object = vec![("kind".to_string(), Json::String(kind.to_string()))];
+    object.extend(
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value)),
+    );
+    Json::Object(object)
+}
+
+fn optional<T>(value: &Option<T>, to_json: impl Fn(&T) -> Json) -> Json {
+    value.as_ref().map(to_json).unwrap_or(Json::Null)
+}
+
+fn statement(stmt: &Statement) -> Json {
+    match stmt {
+        Statement::Print(keyword, e) => node(
+            "Print",
+            vec![("keyword", token(keyword)), ("expr", expr(e))],
+        ),
+        Statement::Expression(e) => node("Expression", vec![("expr", expr(e))]),
+        Statement::Var(name, init) => node(
+            "Var",
+            vec![("name", token(name)), ("init", optional(init, expr))],
+        ),
+        Statement::Block(stmts) => node("Block", vec![("body", to_json(stmts))]),
+        Statement::If(keyword, cond, then_branch, else_branch) => node(
+            "If",
+            vec![
+                ("keyword", token(keyword)),
+                ("cond", expr(cond)),
+                ("then", statement(then_branch)),
+                ("else", optional(else_branch, |s| statement(s))),
+            ],
+        ),
+        Statement::While(keyword, cond, body) => node(
+            "While",
+            vec![
+                ("keyword", token(keyword)),
+                ("cond", expr(cond)),
+                ("body", statement(body)),
+            ],
+        ),
+        Statement::For(keyword, init, cond, increment, body) => node(
+            "For",
+            vec![
+                ("keyword", token(keyword)),
+                ("init", optional(init, |s| statement(s))),
+                ("cond", optional(cond, expr)),
+                ("increment", optional(increment, expr)),
+                ("body", statement(body)),
+            ],
+        ),
+        Statement::Function(name, params, body) => node(
+            "Function",
+            vec![
+                ("name", token(name)),
+                ("params", Json::Array(params.iter().map(token).collect())),
+                ("body", to_json(body)),
+            ],
+        ),
+        Statement::Return(keyword, value) => node(
+            "Return",
+            vec![
+                ("keyword", token(keyword)),
+                ("value", optional(value, expr)),
+            ],
+        ),
+        Statement::Comment(comment, trailing) => node(
+            "Comment",
+            vec![
+                ("token", token(comment)),
+                ("trailing", Json::Bool(*trailing)),
+            ],
+        ),
+    }
+}
+
+fn expr(expr: &Expr) -> Json {
+    match expr {
+        Expr::Literal(obj, lexeme) => node(
+            "Literal",
+            vec![
+                ("value", object(obj)),
+                (
+                    "lexeme",
+                    optional(lexeme, |lexeme| Json::String(lexeme.clone())),
+                ),
+            ],
+        ),
+        Expr::Unary(op, right) => node(
+            "Unary",
+            vec![("operator", token(op)), ("right", self::expr(right))],
+        ),
+        Expr::Binary(left, op, right) => node(
+            "Binary",
+            vec![
+                ("left", self::expr(left)),
+                ("operator", token(op)),
+                ("right", self::expr(right)),
+            ],
+        ),
+        Expr::Grouping(inner) => node("Grouping", vec![("expr", self::expr(inner))]),
+        Expr::Variable(name) => node("Variable", vec![("name", token(name))]),
+        Expr::Assign(name, value) => node(
+            "Assign",
+            vec![("name", token(name)), ("value", self::expr(value))],
+        ),
+        Expr::CompoundAssign(name, op, value) => node(
+            "CompoundAssign",
+            vec![
+                ("name", token(name)),
+                ("operator", token(op)),
+                ("value", self::expr(value)),
+            ],
+        ),
+        Expr::Prefix(op, name) => node(
+            "Prefix",
+            vec![("operator", token(op)), ("name", token(name))],
+        ),
+        Expr::Postfix(name, op) => node(
+            "Postfix",
+            vec![("name", token(name)), ("operator", token(op))],
+        ),
+        Expr::Logical(left, op, right) => node(
+            "Logical",
+            vec![
+                ("left", self::expr(left)),
+                ("operator", token(op)),
+                ("right", self::expr(right)),
+            ],
+        ),
+        Expr::Conditional(cond, then_expr, else_expr) => node(
+            "Conditional",
+            vec![
+                ("cond", self::expr(cond)),
+                ("then", self::expr(then_expr)),
+                ("else", self::expr(else_expr)),
+            ],
+        ),
+        Expr::Call(callee, paren, args) => node(
+            "Call",
+            vec![
+                ("callee", self::expr(callee)),
+                ("paren", token(paren)),
+                ("args", Json::Array(args.iter().map(self::expr).collect())),
+            ],
+        ),
+        Expr::Interpolation(parts) => node(
+            "Interpolation",
+            vec![("parts", Json::Array(parts.iter().map(self::expr).collect()))],
+        ),
+        Expr::Commented(inner, comments) => node(
+            "Commented",
+            vec![
+                ("expr", self::expr(inner)),
+                (
+                    "comments",
+                    Json::Array(comments.iter().map(token).collect()),
+                ),
+            ],
+        ),
+    }
+}
+
+fn token(token: &Token) -> Json {
+    Json::Object(vec![
+        ("type".to_string(), Json::String(token.0.to_string())),
+        ("lexeme".to_string(), Json::String(token.1.clone())),
+        ("literal".to_string(), optional(&token.2, object)),
+        ("line".to_string(), Json::Number(token.3 as f64)),
+        ("offset".to_string(), Json::Number(token.4 as f64)),
+    ])
+}
+
+fn object(obj: &Object) -> Json {
+    match obj {
+        Object::String(s) => Json::String(s.clone()),
+        Object::Number(n) if n.is_finite() => Json::Number(*n),
+        // JSON has no infinities or NaN, so they're tagged like integers.
+        Object::Number(n) => {
+            let number = if n.is_nan() {
+                "NaN"
+            } else if *n > 0.0 {
+                "Infinity"
+            } else {
+                "-Infinity"
+            };
+            Json::Object(vec![(
+                "number".to_string(),
+                Json::String(number.to_string()),
+            )])
+        }
+        // Kept as a string since JSON numbers can't hold every i64 exactly.
+        Object::Integer(n) => {
+            Json::Object(vec![("integer".to_string(), Json::String(n.to_string()))])
+        }
+        Object::Boolean(b) => Json::Bool(*b),
+        Object::Nil => Json::Null,
+        Object::Callable(callable) => Json::String(callable.to_string()),
+        Object::List(items) => Json::Array(items.iter().map(object).collect()),
+    }
+}
+
+fn to_statement(json: &Json) -> Result<Statement, JsonError> {
+    let stmt = match json.field("kind")?.as_str()? {
+        "Print" => Statement::Print(
+            to_token(json.field("keyword")?)?,
+            to_expr(json.field("expr")?)?,
+        ),
+        "Expression" => Statement::Expression(to_expr(json.field("expr")?)?),
+        "Var" => Statement::Var(
+            to_token(json.field("name")?)?,
+            to_optional(json.field("init")?, to_expr)?,
+        ),
+        "Block" => Statement::Block(from_json(json.field("body")?)?),
+        "If" => Statement::If(
+            to_token(json.field("keyword")?)?,
+            to_expr(json.field("cond")?)?,
+            Box::new(to_statement(json.field("then")?)?),
+            to_optional(json.field("else")?, to_statement)?.map(Box::new),
+        ),
+        "While" => Statement::While(
+            to_token(json.field("keyword")?)?,
+            to_expr(json.field("cond")?)?,
+            Box::new(to_statement(json.field("body")?)?),
+        ),
+        "For" => Statement::For(
+            to_token(json.field("keyword")?)?,
+            to_optional(json.field("init")?, to_statement)?.map(Box::new),
+            to_optional(json.field("cond")?, to_expr)?,
+            to_optional(json.field("increment")?, to_expr)?,
+            Box::new(to_statement(json.field("body")?)?),
+        ),
+        "Function" => Statement::Function(
+            to_token(json.field("name")?)?,
+            json.field("params")?
+                .as_array()?
+                .iter()
+                .map(to_token)
+                .collect::<Result<_, _>>()?,
+            from_json(json.field("body")?)?,
+        ),
+        "Return" => Statement::Return(
+            to_token(json.field("keyword")?)?,
+            to_optional(json.field("value")?, to_expr)?,
+        ),
+        "Comment" => Statement::Comment(
+            to_token(json.field("token")?)?,
+            json.field("trailing")?.as_bool()?,
+        ),
+        kind => return Err(JsonError::Shape(format!("unknown statement '{}'", kind))),
+    };
+
+    Ok(stmt)
+}
+
+fn to_expr(json: &Json) -> Result<Expr, JsonError> {
+    let expr = match json.field("kind")?.as_str()? {
+        "Literal" => Expr::Literal(
+            to_object(json.field("value")?)?,
+            to_optional(json.field("lexeme")?, |lexeme| {
+                Ok(lexeme.as_str()?.to_string())
+            })?,
+        ),
+        "Unary" => Expr::Unary(
+            to_token(json.field("operator")?)?,
+            Box::new(to_expr(json.field("right")?)?),
+        ),
+        "Binary" => Expr::Binary(
+            Box::new(to_expr(json.field("left")?)?),
+            to_token(json.field("operator")?)?,
+            Box::new(to_expr(json.field("right")?)?),
+        ),
+        "Grouping" => Expr::Grouping(Box::new(to_expr(json.field("expr")?)?)),
+        "Variable" => Expr::Variable(to_token(json.field("name")?)?),
+        "Assign" => Expr::Assign(
+            to_token(json.field("name")?)?,
+            Box::new(to_expr(json.field("value")?)?),
+        ),
+        "CompoundAssign" => Expr::CompoundAssign(
+            to_token(json.field("name")?)?,
+            to_token(json.field("operator")?)?,
+            Box::new(to_expr(json.field("value")?)?),
+        ),
+        "Prefix" => Expr::Prefix(
+            to_token(json.field("operator")?)?,
+            to_token(json.field("name")?)?,
+        ),
+        "Postfix" => Expr::Postfix(
+            to_token(json.field("name")?)?,
+            to_token(json.field("operator")?)?,
+        ),
+        "Logical" => Expr::Logical(
+            Box::new(to_expr(json.field("left")?)?),
+            to_token(json.field("operator")?)?,
+            Box::new(to_expr(json.field("right")?)?),
+        ),
+        "Conditional" => Expr::Conditional(
+            Box::new(to_expr(json.field("cond")?)?),
+            Box::new(to_expr(json.field("then")?)?),
+            Box::new(to_expr(json.field("else")?)?),
+        ),
+        "Call" => Expr::Call(
+            Box::new(to_expr(json.field("callee")?)?),
+            to_token(json.field("paren")?)?,
+            json.field("args")?
+                .as_array()?
+                .iter()
+                .map(to_expr)
+                .collect::<Result<_, _>>()?,
+        ),
+        "Interpolation" => Expr::Interpolation(
+            json.field("parts")?
+                .as_array()?
+                .iter()
+                .map(to_expr)
+                .collect::<Result<_, _>>()?,
+        ),
+        "Commented" => Expr::Commented(
+            Box::new(to_expr(json.field("expr")?)?),
+            json.field("comments")?
+                .as_array()?
+                .iter()
+                .map(to_token)
+                .collect::<Result<_, _>>()?,
+        ),
+        kind => return Err(JsonError::Shape(format!("unknown expression '{}'", kind))),
+    };
+
+    Ok(expr)
+}
+
+fn to_token(json: &Json) -> Result<Token, JsonError> {
+    let token_type: TokenType = json
+        .field("type")?
+        .as_str()?
+        .parse()
+        .map_err(JsonError::Shape)?;
+
+    Ok(Token::new(
+        token_type,
+        json.field("lexeme")?.as_str()?.to_string(),
+        to_optional(json.field("literal")?, to_object)?,
+        json.field("line")?.as_f64()? as usize,
+        json.field("offset")?.as_f64()? as usize,
+    ))
+}
+
+fn to_object(json: &Json) -> Result<Object, JsonError> {
+    let obj = match json {
+        Json::String(s) => Object::String(s.clone()),
+        Json::Number(n) => Object::Number(*n),
+        Json::Bool(b) => Object::Boolean(*b),
+        Json::Null => Object::Nil,
+        Json::Object(_) if json.field("number").is_ok() => {
+            let number = json.field("number")?.as_str()?;
+            match number {
+                "NaN" => Object::Number(f64::NAN),
+                "Infinity" => Object::Number(f64::INFINITY),
+                "-Infinity" => Object::Number(f64::NEG_INFINITY),
+                _ => return Err(JsonError::Shape(format!("invalid number '{}'", number))),
+            }
+        }
+        Json::Object(_) => {
+            let integer = json.field("integer")?.as_str()?;
+            Object::Integer(
+                integer
+                    .parse()
+                    .map_err(|_| JsonError::Shape(format!("invalid integer '{}'", integer)))?,
+            )
+        }
+        _ => return Err(JsonError::Shape("expected literal value".to_string())),
+    };
+
+    Ok(obj)
+}
+
+fn to_optional<T>(
+    json: &Json,
+    convert: impl Fn(&Json) -> Result<T, JsonError>,
+) -> Result<Option<T>, JsonError> {
+    match json {
+        Json::Null => Ok(None),
+        json => convert(json).map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_json, to_json};
+    use crate::{
+        interpreter::Object,
+        json::Json,
+        parse::{Expr, Statement},
+        testing::parse_with_comments,
+    };
+
+    /// Covers every statement and expression kind.
+    const SOURCE: &str = r#"// leading
+var a = 1; // trailing
+var b;
+fun f(x, y) {
+  return x;
+  return;
+}
+{ print -a + (2.5 * 3); }
+if (a and !b or nil) print "s${a}t"; else a = 2;
+while (a < 10) a += 1;
+for (var i = 0; i < 3; i++) --a;
+for (;;) true ? f(1, // arg
+  2) : false;
+"#;
+
+    fn round_trip(stmts: &[Statement]) -> Vec<Statement> {
+        let json = Json::parse(&to_json(stmts).to_string()).unwrap();
+        from_json(&json).unwrap()
+    }
+
+    #[test]
+    fn round_trips_every_node() {
+        let stmts = parse_with_comments(SOURCE);
+        let loaded = round_trip(&stmts);
+        assert_eq!(to_json(&loaded).to_string(), to_json(&stmts).to_string());
+    }
+
+    #[test]
+    fn round_trips_non_finite_numbers() {
+        let values = [f64::INFINITY, f64::NEG_INFINITY, f64::NAN];
+        let stmts: Vec<Statement> = values
+            .iter()
+            .map(|n| Statement::Expression(Expr::Literal(Object::Number(*n), None)))
+            .collect();
+
+        let loaded: Vec<f64> = round_trip(&stmts)
+            .iter()
+            .map(|stmt| match stmt {
+                Statement::Expression(Expr::Literal(Object::Number(n), None)) => *n,
+                stmt => panic!("unexpected {}", stmt),
+            })
+            .collect();
+        assert_eq!(loaded[0], f64::INFINITY);
+        assert_eq!(loaded[1], f64::NEG_INFINITY);
+        assert!(loaded[2].is_nan());
+    }
+
+    #[test]
+    fn requires_lexemes_and_offsets() {
+        let json = to_json(&parse_with_comments("print 1;")).to_string();
+        for field in [r#","lexeme":"1""#, r#","offset":0"#] {
+            let json = Json::parse(&json.replace(field, "")).unwrap();
+            assert!(from_json(&json).is_err(), "{}", field);
+        }
+    }
+}
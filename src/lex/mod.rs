@@ -13,6 +13,24 @@ pub enum TokenizerError {
     UnexpectedCharacter(usize, char),
     #[error("[line {0}] Error: Unterminated string.")]
     UnexpectedString(usize),
+    #[error("[line {0}] Error: Invalid escape sequence '{1}'.")]
+    InvalidEscape(usize, String),
+    #[error("[line {0}] Error: Unterminated block comment.")]
+    UnterminatedComment(usize),
+    #[error("[line {0}] Error: Malformed number literal '{1}'.")]
+    MalformedNumber(usize, String),
+}
+
+impl TokenizerError {
+    pub fn line(&self) -> usize {
+        match self {
+            TokenizerError::UnexpectedCharacter(line, _)
+            | TokenizerError::UnexpectedString(line)
+            | TokenizerError::InvalidEscape(line, _)
+            | TokenizerError::UnterminatedComment(line)
+            | TokenizerError::MalformedNumber(line, _) => *line,
+        }
+    }
 }
 
 pub struct Tokenizer<'a> {
@@ -20,6 +38,12 @@ pub struct Tokenizer<'a> {
     tokens: Vec<Token>,
     error: Vec<TokenizerError>,
     line: usize,
+    /// Byte offsets of the next character and of the current token.
+    offset: usize,
+    start: usize,
+    keep_comments: bool,
+    /// Brace depth inside each `${...}` currently being lexed.
+    interpolations: Vec<usize>,
 }
 
 impl<'a> Tokenizer<'a> {
@@ -29,11 +53,22 @@ impl<'a> Tokenizer<'a> {
             tokens: Vec::new(),
             error: Vec::new(),
             line: 1,
+            offset: 0,
+            start: 0,
+            keep_comments: false,
+            interpolations: Vec::new(),
         }
     }
 
+    /// Emits `//` comments as `Comment` tokens instead of discarding them.
+    pub fn with_comments(mut self) -> Self {
+        self.keep_comments = true;
+        self
+    }
+
     pub fn tokenize(mut self) -> (Vec<Token>, Vec<TokenizerError>) {
         while !self.is_at_end() {
+            self.start = self.offset;
             match self.next_token() {
                 Ok(Some(token)) => self.tokens.push(token),
                 Ok(None) => continue,
@@ -41,6 +76,13 @@ impl<'a> Tokenizer<'a> {
             }
         }
 
+        // A string opened inside the interpolation has already been reported.
+        let reported = matches!(self.error.last(), Some(TokenizerError::UnexpectedString(_)));
+        if !self.interpolations.is_empty() && !reported {
+            self.error.push(TokenizerError::UnexpectedString(self.line));
+        }
+
+        self.start = self.offset;
         self.tokens.push(self.new_token(TokenType::Eof, ""));
 
         (self.tokens, self.error)
@@ -49,28 +91,94 @@ impl<'a> Tokenizer<'a> {
     fn next_token(&mut self) -> Result<Option<Token>, TokenizerError> {
         let c = self.next().unwrap();
         let token = match c {
-            '{' => self.new_token(TokenType::LeftBrace, "{"),
-            '}' => self.new_token(TokenType::RightBrace, "}"),
+            '{' => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+                self.new_token(TokenType::LeftBrace, "{")
+            }
+            '}' => match self.interpolations.last_mut() {
+                Some(0) => {
+                    self.interpolations.pop();
+                    self.match_string('}')?
+                }
+                Some(depth) => {
+                    *depth -= 1;
+                    self.new_token(TokenType::RightBrace, "}")
+                }
+                None => self.new_token(TokenType::RightBrace, "}"),
+            },
             '(' => self.new_token(TokenType::LeftParen, "("),
             ')' => self.new_token(TokenType::RightParen, ")"),
             ',' => self.new_token(TokenType::Comma, ","),
             '.' => self.new_token(TokenType::Dot, "."),
-            '-' => self.new_token(TokenType::Minus, "-"),
-            '+' => self.new_token(TokenType::Plus, "+"),
+            '-' => match self.peek() {
+                Some('-') => {
+                    self.next();
+                    self.new_token(TokenType::MinusMinus, "--")
+                }
+                _ => self.combine_or('-', '=', TokenType::MinusEqual, TokenType::Minus),
+            },
+            '+' => match self.peek() {
+                Some('+') => {
+                    self.next();
+                    self.new_token(TokenType::PlusPlus, "++")
+                }
+                _ => self.combine_or('+', '=', TokenType::PlusEqual, TokenType::Plus),
+            },
+            '%' => self.new_token(TokenType::Percent, "%"),
+            '~' => match self.peek() {
+                Some('/') => {
+                    self.next();
+                    self.new_token(TokenType::TildeSlash, "~/")
+                }
+                _ => self.new_token(TokenType::Tilde, "~"),
+            },
+            '&' => self.new_token(TokenType::Ampersand, "&"),
+            '|' => self.new_token(TokenType::Pipe, "|"),
+            '^' => self.new_token(TokenType::Caret, "^"),
             ';' => self.new_token(TokenType::Semicolon, ";"),
+            '?' => self.new_token(TokenType::Question, "?"),
+            ':' => self.new_token(TokenType::Colon, ":"),
             '/' => {
-                if let Some('/') = self.peek() {
-                    self.skip_comment();
-                    return Ok(None);
+                let comment = match self.peek() {
+                    Some('/') => self.match_comment(),
+                    Some('*') => self.match_block_comment()?,
+                    _ => {
+                        let token =
+                            self.combine_or('/', '=', TokenType::SlashEqual, TokenType::Slash);
+                        return Ok(Some(token));
+                    }
+                };
+                if self.keep_comments {
+                    return Ok(Some(comment));
                 }
-                self.new_token(TokenType::Slash, "/")
+                return Ok(None);
             }
-            '*' => self.new_token(TokenType::Star, "*"),
+            '*' => match self.peek() {
+                Some('*') => {
+                    self.next();
+                    self.new_token(TokenType::StarStar, "**")
+                }
+                _ => self.combine_or('*', '=', TokenType::StarEqual, TokenType::Star),
+            },
             '=' => self.combine_or('=', '=', TokenType::EqualEqual, TokenType::Equal),
             '!' => self.combine_or('!', '=', TokenType::BangEqual, TokenType::Bang),
-            '<' => self.combine_or('<', '=', TokenType::LessEqual, TokenType::Less),
-            '>' => self.combine_or('>', '=', TokenType::GreaterEqual, TokenType::Greater),
-            '"' => self.match_string()?,
+            '<' => match self.peek() {
+                Some('<') => {
+                    self.next();
+                    self.new_token(TokenType::LessLess, "<<")
+                }
+                _ => self.combine_or('<', '=', TokenType::LessEqual, TokenType::Less),
+            },
+            '>' => match self.peek() {
+                Some('>') => {
+                    self.next();
+                    self.new_token(TokenType::GreaterGreater, ">>")
+                }
+                _ => self.combine_or('>', '=', TokenType::GreaterEqual, TokenType::Greater),
+            },
+            '"' => self.match_string('"')?,
             n if n.is_ascii_digit() => self.match_number(n)?,
             s if s.is_alphabetic() || s == '_' => self.match_identifier(s)?,
             s if s.is_whitespace() => return Ok(None),
@@ -100,67 +208,259 @@ impl<'a> Tokenizer<'a> {
     }
 
     fn match_number(&mut self, curr: char) -> Result<Token, TokenizerError> {
+        if curr == '0' {
+            if let Some(&prefix @ ('x' | 'X' | 'b' | 'B')) = self.peek() {
+                return self.match_radix_number(prefix);
+            }
+        }
+
         let mut number = String::new();
         number.push(curr);
+        self.take_digits(&mut number);
+
+        if let Some('.') = self.peek() {
+            number.push('.');
+            self.next();
+            self.take_digits(&mut number);
+        }
 
-        while let Some(c) = self.peek() {
-            if c.is_ascii_digit() {
-                number.push(*c);
+        if let Some(&exponent @ ('e' | 'E')) = self.peek() {
+            number.push(exponent);
+            self.next();
+            if let Some(&sign @ ('+' | '-')) = self.peek() {
+                number.push(sign);
                 self.next();
-            } else {
-                break;
             }
+            self.take_digits(&mut number);
         }
 
-        if let Some('.') = self.peek() {
-            number.push('.');
-            self.next();
-            while let Some(c) = self.peek() {
-                if c.is_ascii_digit() {
-                    number.push(*c);
-                    self.next();
-                } else {
-                    break;
-                }
+        // Whole numbers are integers unless they don't fit in an i64.
+        // Literals too large for a float are rejected rather than read as
+        // infinity.
+        let value = Some(&number)
+            .filter(|number| separated(number, 10))
+            .map(|number| number.replace('_', ""))
+            .and_then(|digits| {
+                digits
+                    .parse()
+                    .map(Object::Integer)
+                    .or_else(|_| digits.parse().map(Object::Number))
+                    .ok()
+            })
+            .filter(|value| !matches!(value, Object::Number(n) if !n.is_finite()))
+            .ok_or_else(|| TokenizerError::MalformedNumber(self.line, number.clone()))?;
+
+        Ok(Token::new(
+            TokenType::Number,
+            number,
+            Some(value),
+            self.line,
+            self.start,
+        ))
+    }
+
+    /// Lexes a `0x` or `0b` literal whose prefix is next in the source.
+    fn match_radix_number(&mut self, prefix: char) -> Result<Token, TokenizerError> {
+        let mut number = format!("0{}", prefix);
+        self.next();
+
+        // Take every alphanumeric so stray digits like `0b102` are rejected
+        // as a whole rather than split into two tokens.
+        while let Some(&c) = self.peek() {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                number.push(c);
+                self.next();
+            } else {
+                break;
             }
         }
 
+        let radix = if prefix.eq_ignore_ascii_case(&'x') {
+            16
+        } else {
+            2
+        };
+        let digits = &number[2..];
+        let value = Some(digits)
+            .filter(|digits| !digits.is_empty() && separated(digits, radix))
+            .and_then(|digits| i64::from_str_radix(&digits.replace('_', ""), radix).ok())
+            .ok_or_else(|| TokenizerError::MalformedNumber(self.line, number.clone()))?;
+
         Ok(Token::new(
             TokenType::Number,
-            number.clone(),
-            Some(Object::Number(number.parse().unwrap())),
+            number,
+            Some(Object::Integer(value)),
             self.line,
+            self.start,
         ))
     }
 
-    fn match_string(&mut self) -> Result<Token, TokenizerError> {
+    fn take_digits(&mut self, number: &mut String) {
+        while let Some(&c) = self.peek() {
+            if c.is_ascii_digit() || c == '_' {
+                number.push(c);
+                self.next();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Lexes a string, or the segment of one that follows an interpolated
+    /// expression when `opening` is `}`. A segment ending in `${` becomes an
+    /// `Interpolation` token and the expression after it is lexed as code.
+    fn match_string(&mut self, opening: char) -> Result<Token, TokenizerError> {
+        let mut lexeme = String::from(opening);
         let mut string = String::new();
+        let mut error = None;
+
         while let Some(c) = self.next() {
-            if c == '"' {
-                return Ok(Token::new(
-                    TokenType::String,
-                    format!("\"{}\"", string),
-                    Some(Object::String(string)),
-                    self.line,
-                ));
+            lexeme.push(c);
+            match c {
+                '"' => {
+                    if let Some(error) = error {
+                        return Err(error);
+                    }
+                    return Ok(Token::new(
+                        TokenType::String,
+                        lexeme,
+                        Some(Object::String(string)),
+                        self.line,
+                        self.start,
+                    ));
+                }
+                '$' if self.peek() == Some(&'{') => {
+                    lexeme.push('{');
+                    self.next();
+                    self.interpolations.push(0);
+                    if let Some(error) = error {
+                        return Err(error);
+                    }
+                    return Ok(Token::new(
+                        TokenType::Interpolation,
+                        lexeme,
+                        Some(Object::String(string)),
+                        self.line,
+                        self.start,
+                    ));
+                }
+                // Keep scanning after a bad escape so the rest of the
+                // string isn't lexed as code.
+                '\\' => match self.match_escape(&mut lexeme) {
+                    Ok(c) => string.push(c),
+                    Err(e) => {
+                        error.get_or_insert(e);
+                    }
+                },
+                c => string.push(c),
             }
+        }
 
-            if c == '\n' {
-                self.line += 1;
+        Err(TokenizerError::UnexpectedString(self.line))
+    }
+
+    fn match_escape(&mut self, lexeme: &mut String) -> Result<char, TokenizerError> {
+        let start = lexeme.len() - 1;
+        let c = self.next();
+        if let Some(c) = c {
+            lexeme.push(c);
+        }
+
+        let decoded = match c {
+            Some('n') => Some('\n'),
+            Some('t') => Some('\t'),
+            Some('r') => Some('\r'),
+            Some('"') => Some('"'),
+            Some('$') => Some('$'),
+            Some('\\') => Some('\\'),
+            Some('u') => self.match_unicode(lexeme),
+            _ => None,
+        };
+
+        decoded.ok_or_else(|| TokenizerError::InvalidEscape(self.line, lexeme[start..].to_string()))
+    }
+
+    /// Decodes the `{XXXX}` part of a `\u{XXXX}` escape.
+    fn match_unicode(&mut self, lexeme: &mut String) -> Option<char> {
+        if self.peek() != Some(&'{') {
+            return None;
+        }
+        lexeme.push('{');
+        self.next();
+
+        let mut digits = String::new();
+        while let Some(&c) = self.peek() {
+            if !c.is_ascii_hexdigit() {
+                break;
             }
+            digits.push(c);
+            lexeme.push(c);
+            self.next();
+        }
 
-            string.push(c);
+        if self.peek() != Some(&'}') {
+            return None;
         }
+        lexeme.push('}');
+        self.next();
 
-        Err(TokenizerError::UnexpectedString(self.line))
+        if digits.is_empty() || digits.len() > 6 {
+            return None;
+        }
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(char::from_u32)
     }
 
-    fn skip_comment(&mut self) {
-        while let Some(c) = self.next() {
+    fn match_comment(&mut self) -> Token {
+        let mut comment = String::from("/");
+        while let Some(&c) = self.peek() {
             if c == '\n' {
                 break;
             }
+            comment.push(c);
+            self.next();
         }
+
+        self.new_token(TokenType::Comment, comment.trim_end())
+    }
+
+    /// Lexes a `/* ... */` comment, which may span lines and nest. The token
+    /// keeps the line the comment starts on.
+    fn match_block_comment(&mut self) -> Result<Token, TokenizerError> {
+        let line = self.line;
+        let mut comment = String::from("/*");
+        self.next();
+
+        let mut depth = 1;
+        while depth > 0 {
+            let Some(c) = self.next() else {
+                return Err(TokenizerError::UnterminatedComment(self.line));
+            };
+            comment.push(c);
+
+            match (c, self.peek()) {
+                ('/', Some('*')) => {
+                    comment.push('*');
+                    self.next();
+                    depth += 1;
+                }
+                ('*', Some('/')) => {
+                    comment.push('/');
+                    self.next();
+                    depth -= 1;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Token::new(
+            TokenType::Comment,
+            comment,
+            None,
+            line,
+            self.start,
+        ))
     }
 
     fn combine_or(
@@ -185,8 +485,11 @@ impl<'a> Tokenizer<'a> {
 
     fn next(&mut self) -> Option<char> {
         let c = self.source.next();
-        if c == Some('\n') {
-            self.line += 1;
+        if let Some(c) = c {
+            self.offset += c.len_utf8();
+            if c == '\n' {
+                self.line += 1;
+            }
         }
         c
     }
@@ -196,10 +499,21 @@ impl<'a> Tokenizer<'a> {
     }
 
     fn new_token(&self, token_type: TokenType, lexeme: &str) -> Token {
-        Token::new(token_type, lexeme.to_string(), None, self.line)
+        Token::new(token_type, lexeme.to_string(), None, self.line, self.start)
     }
 }
 
+/// Underscores may only appear between two digits, as in `1_000`.
+fn separated(number: &str, radix: u32) -> bool {
+    let chars: Vec<char> = number.chars().collect();
+    let digit = |i: usize| chars[i].is_digit(radix);
+
+    chars
+        .iter()
+        .enumerate()
+        .all(|(i, &c)| c != '_' || (i > 0 && i + 1 < chars.len() && digit(i - 1) && digit(i + 1)))
+}
+
 fn match_reserved(identifier: &str) -> Option<TokenType> {
     let ident = match identifier {
         "and" => TokenType::And,
@@ -223,3 +537,204 @@ fn match_reserved(identifier: &str) -> Option<TokenType> {
 
     Some(ident)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Tokenizer;
+    use crate::{interpreter::Object, lex::TokenType, testing::tokenize};
+
+    fn errors(source: &str) -> Vec<String> {
+        let (_, errors) = Tokenizer::new(source).tokenize();
+        errors.iter().map(ToString::to_string).collect()
+    }
+
+    fn string(source: &str) -> String {
+        match &tokenize(source)[0].2 {
+            Some(Object::String(s)) => s.clone(),
+            _ => panic!("{} is not a string", source),
+        }
+    }
+
+    #[test]
+    fn decodes_escapes() {
+        let escapes = [
+            (r#""\n""#, "\n"),
+            (r#""\t""#, "\t"),
+            (r#""\r""#, "\r"),
+            (r#""\"""#, "\""),
+            (r#""\$""#, "$"),
+            (r#""\\""#, "\\"),
+            (r#""\u{48}""#, "H"),
+            (r#""\u{1F600}""#, "\u{1F600}"),
+        ];
+        for (source, value) in escapes {
+            assert_eq!(string(source), value, "{}", source);
+            assert_eq!(tokenize(source)[0].1, source);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_escapes() {
+        let escapes = [
+            (r"\q", r"\q"),
+            (r"\u48", r"\u"),
+            (r"\u{}", r"\u{}"),
+            (r"\u{1234567}", r"\u{1234567}"),
+            (r"\u{110000}", r"\u{110000}"),
+            (r"\u{D800}", r"\u{D800}"),
+        ];
+        for (written, escape) in escapes {
+            let source = format!("\"a{}b\" print", written);
+            assert_eq!(
+                errors(&source),
+                [format!(
+                    "[line 1] Error: Invalid escape sequence '{}'.",
+                    escape
+                )],
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn reports_string_lines() {
+        assert_eq!(
+            errors("\"a\nb\\q\""),
+            ["[line 2] Error: Invalid escape sequence '\\q'."]
+        );
+        assert_eq!(errors("\n\"a\nb"), ["[line 3] Error: Unterminated string."]);
+
+        let tokens = tokenize("\"a\nb\" x");
+        assert_eq!((tokens[0].0, tokens[0].3), (TokenType::String, 2));
+        assert_eq!((tokens[1].0, tokens[1].3), (TokenType::Identifier, 2));
+    }
+
+    fn types(source: &str) -> Vec<TokenType> {
+        tokenize(source).iter().map(|token| token.0).collect()
+    }
+
+    #[test]
+    fn lexes_interpolations() {
+        use TokenType::*;
+
+        assert_eq!(types(r#""${a}""#), [Interpolation, Identifier, String, Eof]);
+        assert_eq!(types(r#""${}""#), [Interpolation, String, Eof]);
+        assert_eq!(
+            types(r#""a${b}c${d}e""#),
+            [
+                Interpolation,
+                Identifier,
+                Interpolation,
+                Identifier,
+                String,
+                Eof
+            ]
+        );
+        // Braces inside the expression don't end it.
+        assert_eq!(
+            types(r#""${ { } }""#),
+            [Interpolation, LeftBrace, RightBrace, String, Eof]
+        );
+        // Neither do braces inside a nested string or interpolation.
+        assert_eq!(
+            types(r#""${"x}" + "${1}"}!""#),
+            [
+                Interpolation,
+                String,
+                Plus,
+                Interpolation,
+                Number,
+                String,
+                String,
+                Eof
+            ]
+        );
+        assert_eq!(
+            tokenize(r#""a${b}c""#)
+                .iter()
+                .map(|token| token.1.as_str())
+                .collect::<Vec<_>>(),
+            [r#""a${"#, "b", r#"}c""#, ""]
+        );
+    }
+
+    #[test]
+    fn rejects_unterminated_interpolations() {
+        assert_eq!(errors(r#""${a"#), ["[line 1] Error: Unterminated string."]);
+        assert_eq!(errors(r#""${a}"#), ["[line 1] Error: Unterminated string."]);
+        assert_eq!(
+            errors("\"${a}\n\n"),
+            ["[line 3] Error: Unterminated string."]
+        );
+    }
+
+    #[test]
+    fn nests_block_comments() {
+        use TokenType::*;
+
+        assert_eq!(types("/* a /* b */ c */ x"), [Identifier, Eof]);
+        assert_eq!(types("x /* a */ y"), [Identifier, Identifier, Eof]);
+        // Only as many closers as openers are consumed.
+        assert_eq!(types("/* a */ */"), [Star, Slash, Eof]);
+
+        let (tokens, _) = Tokenizer::new("/* a /* b */ c */")
+            .with_comments()
+            .tokenize();
+        assert_eq!(tokens[0].1, "/* a /* b */ c */");
+    }
+
+    #[test]
+    fn counts_lines_in_block_comments() {
+        let tokens = tokenize("/* a\nb\nc */ x\n/*\n*/ y");
+        assert_eq!((tokens[0].1.as_str(), tokens[0].3), ("x", 3));
+        assert_eq!((tokens[1].1.as_str(), tokens[1].3), ("y", 5));
+    }
+
+    #[test]
+    fn rejects_unterminated_block_comments() {
+        assert_eq!(
+            errors("/* a"),
+            ["[line 1] Error: Unterminated block comment."]
+        );
+        assert_eq!(
+            errors("x\n/* a\n/* b */\n"),
+            ["[line 4] Error: Unterminated block comment."]
+        );
+    }
+
+    fn number(source: &str) -> Object {
+        tokenize(source)[0].2.clone().unwrap()
+    }
+
+    #[test]
+    fn lexes_numbers() {
+        assert!(matches!(number("0x1F"), Object::Integer(31)));
+        assert!(matches!(number("0B1010"), Object::Integer(10)));
+        assert!(matches!(number("1_000"), Object::Integer(1000)));
+        assert!(matches!(number("1.5e3"), Object::Number(n) if n == 1500.0));
+        assert!(matches!(number("1e-3"), Object::Number(n) if n == 0.001));
+        // Too large for an i64, but fine as a float.
+        assert!(
+            matches!(number("9223372036854775808"), Object::Number(n) if n == 9.223372036854776e18)
+        );
+        assert_eq!(tokenize("0x1F")[0].1, "0x1F");
+    }
+
+    #[test]
+    fn rejects_malformed_numbers() {
+        for source in [
+            "0b102", "0x", "0xG", "1__0", "1_", "1._5", "1e", "1e+", "1e400", "1e-", "0x_1",
+        ] {
+            assert_eq!(
+                errors(source),
+                [format!(
+                    "[line 1] Error: Malformed number literal '{}'.",
+                    source
+                )],
+                "{}",
+                source
+            );
+        }
+    }
+}
@@ -1,9 +1,9 @@
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr};
 
 use crate::interpreter::Object;
 
 #[allow(dead_code)]
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum TokenType {
     // Single-character tokens.
     LeftBrace,
@@ -15,8 +15,15 @@ pub enum TokenType {
     Minus,
     Plus,
     Semicolon,
+    Question,
+    Colon,
     Slash,
     Star,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
 
     // One or two character tokens.
     Bang,
@@ -27,11 +34,24 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    /// Floor division, since `//` already starts a comment.
+    TildeSlash,
+    StarStar,
+    LessLess,
+    GreaterGreater,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PlusPlus,
+    MinusMinus,
 
     // Literals.
     Identifier,
     String,
     Number,
+    Interpolation,
+    Comment,
 
     // Keywords.
     And,
@@ -54,9 +74,70 @@ pub enum TokenType {
     Eof,
 }
 
-impl Display for TokenType {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let output = match self {
+impl TokenType {
+    pub const ALL: [TokenType; 58] = [
+        TokenType::LeftBrace,
+        TokenType::RightBrace,
+        TokenType::LeftParen,
+        TokenType::RightParen,
+        TokenType::Comma,
+        TokenType::Dot,
+        TokenType::Minus,
+        TokenType::Plus,
+        TokenType::Semicolon,
+        TokenType::Question,
+        TokenType::Colon,
+        TokenType::Slash,
+        TokenType::Star,
+        TokenType::Percent,
+        TokenType::Ampersand,
+        TokenType::Pipe,
+        TokenType::Caret,
+        TokenType::Tilde,
+        TokenType::Bang,
+        TokenType::BangEqual,
+        TokenType::Equal,
+        TokenType::EqualEqual,
+        TokenType::Greater,
+        TokenType::GreaterEqual,
+        TokenType::Less,
+        TokenType::LessEqual,
+        TokenType::TildeSlash,
+        TokenType::StarStar,
+        TokenType::LessLess,
+        TokenType::GreaterGreater,
+        TokenType::PlusEqual,
+        TokenType::MinusEqual,
+        TokenType::StarEqual,
+        TokenType::SlashEqual,
+        TokenType::PlusPlus,
+        TokenType::MinusMinus,
+        TokenType::Identifier,
+        TokenType::String,
+        TokenType::Number,
+        TokenType::Interpolation,
+        TokenType::Comment,
+        TokenType::And,
+        TokenType::Class,
+        TokenType::Else,
+        TokenType::False,
+        TokenType::Fun,
+        TokenType::For,
+        TokenType::If,
+        TokenType::Nil,
+        TokenType::Or,
+        TokenType::Print,
+        TokenType::Return,
+        TokenType::Super,
+        TokenType::This,
+        TokenType::True,
+        TokenType::Var,
+        TokenType::While,
+        TokenType::Eof,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
             TokenType::LeftBrace => "LEFT_BRACE",
             TokenType::RightBrace => "RIGHT_BRACE",
             TokenType::LeftParen => "LEFT_PAREN",
@@ -66,8 +147,15 @@ impl Display for TokenType {
             TokenType::Minus => "MINUS",
             TokenType::Plus => "PLUS",
             TokenType::Semicolon => "SEMICOLON",
+            TokenType::Question => "QUESTION",
+            TokenType::Colon => "COLON",
             TokenType::Slash => "SLASH",
             TokenType::Star => "STAR",
+            TokenType::Percent => "PERCENT",
+            TokenType::Ampersand => "AMPERSAND",
+            TokenType::Pipe => "PIPE",
+            TokenType::Caret => "CARET",
+            TokenType::Tilde => "TILDE",
             TokenType::Bang => "BANG",
             TokenType::BangEqual => "BANG_EQUAL",
             TokenType::Equal => "EQUAL",
@@ -76,9 +164,21 @@ impl Display for TokenType {
             TokenType::GreaterEqual => "GREATER_EQUAL",
             TokenType::Less => "LESS",
             TokenType::LessEqual => "LESS_EQUAL",
+            TokenType::TildeSlash => "TILDE_SLASH",
+            TokenType::StarStar => "STAR_STAR",
+            TokenType::LessLess => "LESS_LESS",
+            TokenType::GreaterGreater => "GREATER_GREATER",
+            TokenType::PlusEqual => "PLUS_EQUAL",
+            TokenType::MinusEqual => "MINUS_EQUAL",
+            TokenType::StarEqual => "STAR_EQUAL",
+            TokenType::SlashEqual => "SLASH_EQUAL",
+            TokenType::PlusPlus => "PLUS_PLUS",
+            TokenType::MinusMinus => "MINUS_MINUS",
             TokenType::Identifier => "IDENTIFIER",
             TokenType::String => "STRING",
             TokenType::Number => "NUMBER",
+            TokenType::Interpolation => "INTERPOLATION",
+            TokenType::Comment => "COMMENT",
             TokenType::And => "AND",
             TokenType::Class => "CLASS",
             TokenType::Else => "ELSE",
@@ -96,14 +196,36 @@ impl Display for TokenType {
             TokenType::Var => "VAR",
             TokenType::While => "WHILE",
             TokenType::Eof => "EOF",
-        };
+        }
+    }
+}
+
+impl Display for TokenType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for TokenType {
+    type Err = String;
 
-        write!(f, "{}", output)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TokenType::ALL
+            .into_iter()
+            .find(|token_type| token_type.name() == s)
+            .ok_or_else(|| format!("Unknown token type '{}'.", s))
     }
 }
 
+/// A token's type, lexeme, literal value, line and byte offset in the source.
 #[derive(Clone)]
-pub struct Token(pub TokenType, pub String, pub Option<Object>, pub usize);
+pub struct Token(
+    pub TokenType,
+    pub String,
+    pub Option<Object>,
+    pub usize,
+    pub usize,
+);
 
 impl Token {
     pub fn new(
@@ -111,8 +233,9 @@ impl Token {
         lexeme: String,
         literal: Option<Object>,
         line: usize,
+        offset: usize,
     ) -> Self {
-        Token(token_type, lexeme, literal, line)
+        Token(token_type, lexeme, literal, line, offset)
     }
 }
 
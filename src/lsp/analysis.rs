@@ -0,0 +1,336 @@
+use std::collections::HashMap;
+
+use crate::{
+    lex::{Token, TokenType, Tokenizer},
+    parse::{Expr, Parser, Statement},
+};
+
+pub enum SymbolKind {
+    Variable,
+    Parameter,
+    Function(Vec<Token>),
+}
+
+pub struct Symbol {
+    pub name: Token,
+    pub kind: SymbolKind,
+    /// Byte range of the whole declaration.
+    pub span: (usize, usize),
+    /// The function this symbol is declared in.
+    pub parent: Option<usize>,
+}
+
+pub struct Diagnostic {
+    pub line: usize,
+    /// Byte range of the offending token, when it is known.
+    pub span: Option<(usize, usize)>,
+    pub message: String,
+}
+
+/// Everything the language server knows about one document: its
+/// declarations, every use of them and the errors found along the way.
+pub struct Analysis {
+    pub symbols: Vec<Symbol>,
+    /// Each use of a name together with the symbol it refers to.
+    pub references: Vec<(Token, usize)>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Analysis {
+    pub fn new(source: &str) -> Self {
+        let (tokens, lex_errors) = Tokenizer::new(source).tokenize();
+        let (stmts, parse_errors) = Parser::from_tokens(tokens.clone()).parse();
+
+        let mut resolver = Resolver {
+            analysis: Analysis {
+                symbols: Vec::new(),
+                references: Vec::new(),
+                diagnostics: Vec::new(),
+            },
+            tokens: &tokens,
+            scopes: Vec::new(),
+            globals: HashMap::new(),
+            unresolved: Vec::new(),
+            function: None,
+        };
+
+        for error in lex_errors {
+            resolver.report_line(error.line(), error.to_string());
+        }
+        for error in parse_errors {
+            resolver.report_line(error.line(), error.to_string());
+        }
+
+        resolver.statements(&stmts);
+        resolver.finish()
+    }
+
+    /// The symbol declared or used at `offset`.
+    pub fn symbol_at(&self, offset: usize) -> Option<usize> {
+        let declared = self
+            .symbols
+            .iter()
+            .position(|symbol| contains(&symbol.name, offset));
+        declared.or_else(|| {
+            self.references
+                .iter()
+                .find(|(token, _)| contains(token, offset))
+                .map(|(_, symbol)| *symbol)
+        })
+    }
+
+    pub fn references_to(&self, symbol: usize) -> impl Iterator<Item = &Token> {
+        self.references
+            .iter()
+            .filter(move |(_, target)| *target == symbol)
+            .map(|(token, _)| token)
+    }
+}
+
+pub fn span(token: &Token) -> (usize, usize) {
+    (token.4, token.4 + token.1.len())
+}
+
+fn contains(token: &Token, offset: usize) -> bool {
+    let (start, end) = span(token);
+    (start..=end).contains(&offset)
+}
+
+struct Resolver<'a> {
+    analysis: Analysis,
+    tokens: &'a [Token],
+    /// Local scopes, mapping names to their symbol and whether the
+    /// declaration has finished.
+    scopes: Vec<HashMap<String, (usize, bool)>>,
+    globals: HashMap<String, usize>,
+    /// Globals used before any declaration; functions may refer to
+    /// globals declared further down.
+    unresolved: Vec<Token>,
+    function: Option<usize>,
+}
+
+impl Resolver<'_> {
+    fn finish(mut self) -> Analysis {
+        for token in std::mem::take(&mut self.unresolved) {
+            if let Some(&symbol) = self.globals.get(&token.1) {
+                self.analysis.references.push((token, symbol));
+            }
+        }
+
+        self.analysis.references.sort_by_key(|(token, _)| token.4);
+        self.analysis
+    }
+
+    fn statements(&mut self, stmts: &[Statement]) {
+        for stmt in stmts {
+            self.statement(stmt);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Print(_, expr) | Statement::Expression(expr) => self.expr(expr),
+            Statement::Var(name, init) => {
+                let span = span(name);
+                if self.scopes.is_empty() {
+                    if let Some(init) = init {
+                        self.expr(init);
+                    }
+                    self.declare(name, SymbolKind::Variable, span);
+                } else {
+                    let symbol = self.declare(name, SymbolKind::Variable, span);
+                    if let Some(init) = init {
+                        self.expr(init);
+                    }
+                    self.define(&name.1, symbol);
+                }
+            }
+            Statement::Block(stmts) => {
+                self.scopes.push(HashMap::new());
+                self.statements(stmts);
+                self.scopes.pop();
+            }
+            Statement::If(_, cond, then_branch, else_branch) => {
+                self.expr(cond);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Statement::While(_, cond, body) => {
+                self.expr(cond);
+                self.statement(body);
+            }
+            Statement::For(_, init, cond, increment, body) => {
+                self.scopes.push(HashMap::new());
+                if let Some(init) = init {
+                    self.statement(init);
+                }
+                if let Some(cond) = cond {
+                    self.expr(cond);
+                }
+                if let Some(increment) = increment {
+                    self.expr(increment);
+                }
+                self.statement(body);
+                self.scopes.pop();
+            }
+            Statement::Function(name, params, body) => {
+                let kind = SymbolKind::Function(params.clone());
+                let symbol = self.declare(name, kind, self.function_span(name));
+                self.define(&name.1, symbol);
+
+                let enclosing = self.function.replace(symbol);
+                self.scopes.push(HashMap::new());
+                for param in params {
+                    let param_symbol = self.declare(param, SymbolKind::Parameter, span(param));
+                    self.define(&param.1, param_symbol);
+                }
+                self.statements(body);
+                self.scopes.pop();
+                self.function = enclosing;
+            }
+            Statement::Return(keyword, value) => {
+                if self.function.is_none() {
+                    self.report(keyword, "Can't return from top-level code.");
+                }
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            Statement::Comment(_, _) => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(_, _) => {}
+            Expr::Unary(_, right) => self.expr(right),
+            Expr::Binary(left, _, right) | Expr::Logical(left, _, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Grouping(inner) => self.expr(inner),
+            Expr::Variable(name) => self.reference(name),
+            Expr::Assign(name, value) | Expr::CompoundAssign(name, _, value) => {
+                self.expr(value);
+                self.reference(name);
+            }
+            Expr::Prefix(_, name) | Expr::Postfix(name, _) => self.reference(name),
+            Expr::Conditional(cond, then_expr, else_expr) => {
+                self.expr(cond);
+                self.expr(then_expr);
+                self.expr(else_expr);
+            }
+            Expr::Call(callee, _, args) => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Expr::Interpolation(parts) => {
+                for part in parts {
+                    self.expr(part);
+                }
+            }
+            Expr::Commented(expr, _) => self.expr(expr),
+        }
+    }
+
+    fn declare(&mut self, name: &Token, kind: SymbolKind, span: (usize, usize)) -> usize {
+        let symbol = self.analysis.symbols.len();
+        self.analysis.symbols.push(Symbol {
+            name: name.clone(),
+            kind,
+            span,
+            parent: self.function,
+        });
+
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                if scope.insert(name.1.clone(), (symbol, false)).is_some() {
+                    self.report(name, "Already a variable with this name in this scope.");
+                }
+            }
+            None => {
+                self.globals.insert(name.1.clone(), symbol);
+            }
+        }
+
+        symbol
+    }
+
+    fn define(&mut self, name: &str, symbol: usize) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), (symbol, true));
+        }
+    }
+
+    fn reference(&mut self, name: &Token) {
+        let local = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.1).copied());
+
+        match local {
+            Some((symbol, defined)) => {
+                if !defined {
+                    self.report(name, "Can't read local variable in its own initializer.");
+                }
+                self.analysis.references.push((name.clone(), symbol));
+            }
+            None => match self.globals.get(&name.1) {
+                Some(&symbol) => self.analysis.references.push((name.clone(), symbol)),
+                None => self.unresolved.push(name.clone()),
+            },
+        }
+    }
+
+    /// Spans a function declaration from `fun` to its closing brace.
+    fn function_span(&self, name: &Token) -> (usize, usize) {
+        let Ok(index) = self.tokens.binary_search_by_key(&name.4, |token| token.4) else {
+            return span(name);
+        };
+
+        let start = match index.checked_sub(1).map(|i| &self.tokens[i]) {
+            Some(keyword) if keyword.0 == TokenType::Fun => keyword.4,
+            _ => name.4,
+        };
+
+        let mut depth = 0;
+        for token in &self.tokens[index..] {
+            match token.0 {
+                TokenType::LeftBrace => depth += 1,
+                TokenType::RightBrace if depth == 1 => return (start, span(token).1),
+                TokenType::RightBrace => depth -= 1,
+                _ => {}
+            }
+        }
+
+        (start, span(name).1)
+    }
+
+    fn report(&mut self, token: &Token, message: &str) {
+        self.analysis.diagnostics.push(Diagnostic {
+            line: token.3,
+            span: Some(span(token)),
+            message: format!("Error at '{}': {}", token.1, message),
+        });
+    }
+
+    /// Records a lexer or parser error, which only knows its line.
+    fn report_line(&mut self, line: usize, message: String) {
+        // Drop the "[line N] " prefix; the editor shows the position itself.
+        let message = match message.split_once("] ") {
+            Some((prefix, rest)) if prefix.starts_with("[line") => rest.to_string(),
+            _ => message,
+        };
+
+        self.analysis.diagnostics.push(Diagnostic {
+            line,
+            span: None,
+            message,
+        });
+    }
+}
@@ -0,0 +1,594 @@
+mod analysis;
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+use analysis::{span, Analysis, SymbolKind};
+
+use crate::{
+    json::{Json, JsonError},
+    lex::Token,
+};
+
+const METHOD_NOT_FOUND: f64 = -32601.0;
+const INVALID_PARAMS: f64 = -32602.0;
+const PARSE_ERROR: f64 = -32700.0;
+
+/// Speaks the Language Server Protocol over stdin and stdout until the
+/// client sends `exit`, returning the process exit code.
+pub fn serve() -> i32 {
+    run(&mut io::stdin().lock(), io::stdout())
+}
+
+/// Handles framed messages from `input` until the client sends `exit`.
+fn run(input: &mut impl BufRead, output: impl Write) -> i32 {
+    let mut server = Server {
+        documents: HashMap::new(),
+        shutdown: false,
+        output,
+    };
+
+    loop {
+        let message = match read_message(input) {
+            Ok(Some(message)) => message,
+            Ok(None) => return 1,
+            Err(e) => {
+                eprintln!("{}", e);
+                return 1;
+            }
+        };
+
+        let result = match Json::parse(&message) {
+            Ok(message) => server.handle(&message),
+            Err(e) => server
+                .error(Json::Null, PARSE_ERROR, &e.to_string())
+                .map(|_| None),
+        };
+
+        match result {
+            Ok(None) => {}
+            Ok(Some(code)) => return code,
+            Err(e) => {
+                eprintln!("{}", e);
+                return 1;
+            }
+        }
+    }
+}
+
+struct Server<W: Write> {
+    /// Open documents by URI.
+    documents: HashMap<String, String>,
+    shutdown: bool,
+    output: W,
+}
+
+impl<W: Write> Server<W> {
+    /// Handles one message, returning an exit code once the client asks
+    /// the server to exit.
+    fn handle(&mut self, message: &Json) -> io::Result<Option<i32>> {
+        let id = message.field("id").ok().cloned();
+        let Ok(method) = message.field("method").and_then(Json::as_str) else {
+            // A response to a request we never send.
+            return Ok(None);
+        };
+        let params = message.field("params").unwrap_or(&Json::Null);
+
+        if method == "exit" {
+            return Ok(Some(if self.shutdown { 0 } else { 1 }));
+        }
+
+        let result = match method {
+            "initialize" => Ok(capabilities()),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            }
+            "textDocument/definition" => self.definition(params),
+            "textDocument/references" => self.references(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/documentSymbol" => self.document_symbols(params),
+            _ => {
+                match self.notification(method, params) {
+                    Ok(Some(uri)) => self.publish_diagnostics(&uri)?,
+                    Ok(None) => {}
+                    Err(e) => eprintln!("{}", e),
+                }
+                if let Some(id) = id {
+                    let message = format!("Unknown method '{}'", method);
+                    self.error(id, METHOD_NOT_FOUND, &message)?;
+                }
+                return Ok(None);
+            }
+        };
+
+        let Some(id) = id else {
+            return Ok(None);
+        };
+        match result {
+            Ok(result) => self.send(object(vec![
+                ("jsonrpc", string("2.0")),
+                ("id", id),
+                ("result", result),
+            ]))?,
+            Err(e) => self.error(id, INVALID_PARAMS, &e.to_string())?,
+        }
+
+        Ok(None)
+    }
+
+    /// Applies a document notification, returning the URI whose
+    /// diagnostics need publishing.
+    fn notification(&mut self, method: &str, params: &Json) -> Result<Option<String>, JsonError> {
+        match method {
+            "textDocument/didOpen" => {
+                let document = params.field("textDocument")?;
+                let uri = document.field("uri")?.as_str()?;
+                let text = document.field("text")?.as_str()?;
+                self.documents.insert(uri.to_string(), text.to_string());
+                Ok(Some(uri.to_string()))
+            }
+            "textDocument/didChange" => {
+                let uri = params.field("textDocument")?.field("uri")?.as_str()?;
+                // Only full-document sync is advertised, so the last change
+                // holds the whole text.
+                let changes = params.field("contentChanges")?.as_array()?;
+                if let Some(change) = changes.last() {
+                    let text = change.field("text")?.as_str()?;
+                    self.documents.insert(uri.to_string(), text.to_string());
+                }
+                Ok(Some(uri.to_string()))
+            }
+            "textDocument/didClose" => {
+                let uri = params.field("textDocument")?.field("uri")?.as_str()?;
+                self.documents.remove(uri);
+                Ok(Some(uri.to_string()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str) -> io::Result<()> {
+        let diagnostics = match self.documents.get(uri) {
+            Some(text) => Analysis::new(text)
+                .diagnostics
+                .iter()
+                .map(|diagnostic| {
+                    let (start, end) = diagnostic
+                        .span
+                        .unwrap_or_else(|| line_span(text, diagnostic.line));
+                    object(vec![
+                        ("range", range(text, start, end)),
+                        ("severity", Json::Number(1.0)),
+                        ("source", string("lox")),
+                        ("message", string(&diagnostic.message)),
+                    ])
+                })
+                .collect(),
+            // Closing a document clears its diagnostics.
+            None => Vec::new(),
+        };
+
+        let notification = object(vec![
+            ("jsonrpc", string("2.0")),
+            ("method", string("textDocument/publishDiagnostics")),
+            (
+                "params",
+                object(vec![
+                    ("uri", string(uri)),
+                    ("diagnostics", Json::Array(diagnostics)),
+                ]),
+            ),
+        ]);
+        self.send(notification)
+    }
+
+    fn definition(&self, params: &Json) -> Result<Json, JsonError> {
+        let (uri, text, offset) = self.position(params)?;
+        let analysis = Analysis::new(text);
+
+        let definition = analysis.symbol_at(offset).map(|symbol| {
+            let (start, end) = span(&analysis.symbols[symbol].name);
+            location(uri, text, start, end)
+        });
+        Ok(definition.unwrap_or(Json::Null))
+    }
+
+    fn references(&self, params: &Json) -> Result<Json, JsonError> {
+        let (uri, text, offset) = self.position(params)?;
+        let include_declaration = params
+            .field("context")
+            .and_then(|context| context.field("includeDeclaration"))
+            .and_then(Json::as_bool)
+            .unwrap_or(true);
+        let analysis = Analysis::new(text);
+
+        let Some(symbol) = analysis.symbol_at(offset) else {
+            return Ok(Json::Array(Vec::new()));
+        };
+
+        let mut locations = Vec::new();
+        if include_declaration {
+            let (start, end) = span(&analysis.symbols[symbol].name);
+            locations.push(location(uri, text, start, end));
+        }
+        for token in analysis.references_to(symbol) {
+            let (start, end) = span(token);
+            locations.push(location(uri, text, start, end));
+        }
+
+        Ok(Json::Array(locations))
+    }
+
+    fn hover(&self, params: &Json) -> Result<Json, JsonError> {
+        let (_, text, offset) = self.position(params)?;
+        let analysis = Analysis::new(text);
+
+        let Some(symbol) = analysis.symbol_at(offset) else {
+            return Ok(Json::Null);
+        };
+        let symbol = &analysis.symbols[symbol];
+        let contents = match &symbol.kind {
+            SymbolKind::Function(params) => format!(
+                "```lox\nfun {}({})\n```\nArity: {}",
+                symbol.name.1,
+                join(params),
+                params.len()
+            ),
+            SymbolKind::Variable => format!("```lox\nvar {}\n```", symbol.name.1),
+            SymbolKind::Parameter => format!("```lox\n{}\n```\nParameter", symbol.name.1),
+        };
+
+        Ok(object(vec![(
+            "contents",
+            object(vec![
+                ("kind", string("markdown")),
+                ("value", string(&contents)),
+            ]),
+        )]))
+    }
+
+    fn document_symbols(&self, params: &Json) -> Result<Json, JsonError> {
+        let uri = params.field("textDocument")?.field("uri")?.as_str()?;
+        let text = self.document(uri)?;
+        let analysis = Analysis::new(text);
+
+        Ok(Json::Array(function_symbols(&analysis, text, None)))
+    }
+
+    /// The document and byte offset a position request points at.
+    fn position<'a>(&'a self, params: &'a Json) -> Result<(&'a str, &'a str, usize), JsonError> {
+        let uri = params.field("textDocument")?.field("uri")?.as_str()?;
+        let text = self.document(uri)?;
+        let offset = offset(text, params.field("position")?)?;
+        Ok((uri, text, offset))
+    }
+
+    fn document(&self, uri: &str) -> Result<&str, JsonError> {
+        self.documents
+            .get(uri)
+            .map(String::as_str)
+            .ok_or_else(|| JsonError::Shape(format!("document '{}' is not open", uri)))
+    }
+
+    fn error(&mut self, id: Json, code: f64, message: &str) -> io::Result<()> {
+        self.send(object(vec![
+            ("jsonrpc", string("2.0")),
+            ("id", id),
+            (
+                "error",
+                object(vec![
+                    ("code", Json::Number(code)),
+                    ("message", string(message)),
+                ]),
+            ),
+        ]))
+    }
+
+    fn send(&mut self, message: Json) -> io::Result<()> {
+        let body = message.to_string();
+        write!(
+            self.output,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.output.flush()
+    }
+}
+
+fn capabilities() -> Json {
+    object(vec![
+        (
+            "capabilities",
+            object(vec![
+                // Full document sync.
+                ("textDocumentSync", Json::Number(1.0)),
+                ("definitionProvider", Json::Bool(true)),
+                ("referencesProvider", Json::Bool(true)),
+                ("hoverProvider", Json::Bool(true)),
+                ("documentSymbolProvider", Json::Bool(true)),
+            ]),
+        ),
+        ("serverInfo", object(vec![("name", string("lox"))])),
+    ])
+}
+
+/// `fun` declarations inside `parent`, each with its nested functions.
+fn function_symbols(analysis: &Analysis, text: &str, parent: Option<usize>) -> Vec<Json> {
+    analysis
+        .symbols
+        .iter()
+        .enumerate()
+        .filter(|(_, symbol)| symbol.parent == parent)
+        .filter_map(|(index, symbol)| {
+            let SymbolKind::Function(params) = &symbol.kind else {
+                return None;
+            };
+            let (start, end) = symbol.span;
+            let (name_start, name_end) = span(&symbol.name);
+
+            Some(object(vec![
+                ("name", string(&symbol.name.1)),
+                ("detail", string(&format!("({})", join(params)))),
+                // SymbolKind.Function
+                ("kind", Json::Number(12.0)),
+                ("range", range(text, start, end)),
+                ("selectionRange", range(text, name_start, name_end)),
+                (
+                    "children",
+                    Json::Array(function_symbols(analysis, text, Some(index))),
+                ),
+            ]))
+        })
+        .collect()
+}
+
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "missing Content-Length header",
+        ));
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Converts an LSP position, which counts UTF-16 code units within a
+/// line, to a byte offset.
+fn offset(text: &str, position: &Json) -> Result<usize, JsonError> {
+    let line = position.field("line")?.as_f64()? as usize;
+    let character = position.field("character")?.as_f64()? as usize;
+
+    let line_start: usize = text.split_inclusive('\n').take(line).map(str::len).sum();
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return Ok(line_start + i);
+        }
+        units += c.len_utf16();
+    }
+
+    Ok(text.len())
+}
+
+fn position(text: &str, offset: usize) -> Json {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+    object(vec![
+        ("line", Json::Number(before.matches('\n').count() as f64)),
+        (
+            "character",
+            Json::Number(before[line_start..].encode_utf16().count() as f64),
+        ),
+    ])
+}
+
+/// Byte range of a 1-based line, without its newline.
+fn line_span(text: &str, line: usize) -> (usize, usize) {
+    let start: usize = text
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(str::len)
+        .sum();
+    let end = text[start..].find('\n').map_or(text.len(), |i| start + i);
+    (start, end)
+}
+
+fn range(text: &str, start: usize, end: usize) -> Json {
+    object(vec![
+        ("start", position(text, start)),
+        ("end", position(text, end)),
+    ])
+}
+
+fn location(uri: &str, text: &str, start: usize, end: usize) -> Json {
+    object(vec![
+        ("uri", string(uri)),
+        ("range", range(text, start, end)),
+    ])
+}
+
+fn object(fields: Vec<(&str, Json)>) -> Json {
+    Json::Object(
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+fn string(s: &str) -> Json {
+    Json::String(s.to_string())
+}
+
+fn join(params: &[Token]) -> String {
+    params
+        .iter()
+        .map(|param| param.1.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run;
+    use crate::json::Json;
+
+    const SOURCE: &str = "fun add(a, b) {\n  return a + b;\n}\nprint add(1, 2);\nprint x y;\n";
+
+    fn frame(message: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", message.len(), message)
+    }
+
+    fn request(id: usize, method: &str, params: &str) -> String {
+        frame(&format!(
+            r#"{{"jsonrpc":"2.0","id":{},"method":"{}","params":{}}}"#,
+            id, method, params
+        ))
+    }
+
+    fn notification(method: &str, params: &str) -> String {
+        frame(&format!(
+            r#"{{"jsonrpc":"2.0","method":"{}","params":{}}}"#,
+            method, params
+        ))
+    }
+
+    fn position(line: usize, character: usize) -> String {
+        format!(
+            r#"{{"textDocument":{{"uri":"file:///a.lox"}},"position":{{"line":{},"character":{}}}}}"#,
+            line, character
+        )
+    }
+
+    /// Runs a session of framed messages, returning the exit code and
+    /// every message the server sent.
+    fn session(messages: &[String]) -> (i32, Vec<Json>) {
+        let input = messages.concat();
+        let mut output = Vec::new();
+        let code = run(&mut input.as_bytes(), &mut output);
+
+        let output = String::from_utf8(output).unwrap();
+        let messages = output
+            .split("Content-Length: ")
+            .skip(1)
+            .map(|message| {
+                let (_, body) = message.split_once("\r\n\r\n").unwrap();
+                Json::parse(body).unwrap()
+            })
+            .collect();
+        (code, messages)
+    }
+
+    fn start(line: &Json) -> (f64, f64) {
+        let start = line.field("range").unwrap().field("start").unwrap();
+        (
+            start.field("line").unwrap().as_f64().unwrap(),
+            start.field("character").unwrap().as_f64().unwrap(),
+        )
+    }
+
+    #[test]
+    fn scripted_session() {
+        let open = format!(
+            r#"{{"textDocument":{{"uri":"file:///a.lox","languageId":"lox","version":1,"text":{}}}}}"#,
+            Json::String(SOURCE.to_string())
+        );
+        let (code, messages) = session(&[
+            request(1, "initialize", "{}"),
+            notification("textDocument/didOpen", &open),
+            // `add` in `print add(1, 2);`
+            request(2, "textDocument/definition", &position(3, 7)),
+            request(3, "textDocument/hover", &position(3, 7)),
+            request(4, "textDocument/references", &position(0, 4)),
+            request(
+                5,
+                "textDocument/documentSymbol",
+                r#"{"textDocument":{"uri":"file:///a.lox"}}"#,
+            ),
+            request(6, "textDocument/formatting", "{}"),
+            request(7, "shutdown", "null"),
+            notification("exit", "null"),
+        ]);
+        assert_eq!(code, 0);
+        assert_eq!(messages.len(), 8);
+
+        let capabilities = messages[0].field("result").unwrap().field("capabilities");
+        assert!(capabilities
+            .unwrap()
+            .field("hoverProvider")
+            .unwrap()
+            .as_bool()
+            .unwrap());
+
+        let diagnostics = messages[1].field("params").unwrap().field("diagnostics");
+        let diagnostics = diagnostics.unwrap().as_array().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(start(&diagnostics[0]).0, 4.0);
+
+        let definition = messages[2].field("result").unwrap();
+        assert_eq!(start(definition), (0.0, 4.0));
+
+        let hover = messages[3]
+            .field("result")
+            .unwrap()
+            .field("contents")
+            .unwrap();
+        let hover = hover.field("value").unwrap().as_str().unwrap();
+        assert!(hover.contains("fun add(a, b)"), "{}", hover);
+        assert!(hover.contains("Arity: 2"), "{}", hover);
+
+        let references = messages[4].field("result").unwrap().as_array().unwrap();
+        let references: Vec<_> = references.iter().map(start).collect();
+        assert_eq!(references, [(0.0, 4.0), (3.0, 6.0)]);
+
+        let symbols = messages[5].field("result").unwrap().as_array().unwrap();
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols[0].field("name").unwrap().as_str().unwrap(), "add");
+
+        let error = messages[6].field("error").unwrap().field("code").unwrap();
+        assert_eq!(error.as_f64().unwrap(), -32601.0);
+
+        assert!(messages[7].field("result").unwrap() == &Json::Null);
+    }
+
+    #[test]
+    fn exit_without_shutdown() {
+        let (code, messages) = session(&[notification("exit", "null")]);
+        assert_eq!(code, 1);
+        assert!(messages.is_empty());
+    }
+
+    #[test]
+    fn malformed_message() {
+        let (_, messages) = session(&[frame("{"), notification("exit", "null")]);
+        let error = messages[0].field("error").unwrap().field("code").unwrap();
+        assert_eq!(error.as_f64().unwrap(), -32700.0);
+    }
+}
@@ -1,18 +1,64 @@
 use std::env;
 use std::fs;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+mod coverage;
+mod debug;
+mod format;
 mod interpreter;
+mod json;
 mod lex;
+mod lsp;
+mod optimize;
 mod parse;
+mod profile;
+#[cfg(test)]
+mod testing;
+
+/// Deep Lox recursion needs far more stack than the main thread has.
+const STACK_SIZE: usize = 512 * 1024 * 1024;
 
 fn main() {
+    let interpreter = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run)
+        .unwrap();
+    if interpreter.join().is_err() {
+        std::process::exit(101);
+    }
+}
+
+fn run() {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 3 {
-        eprintln!("Usage: {} tokenize <filename>", args[0]);
-        return;
+    if args.get(1).map(String::as_str) == Some("lsp") {
+        std::process::exit(lsp::serve());
     }
+    let Some((command, accepted)) = args
+        .get(1)
+        .and_then(|command| COMMANDS.iter().find(|(name, _)| name == command))
+    else {
+        usage(&args[0]);
+    };
 
-    let command = &args[1];
-    let filename = &args[2];
+    let flags: Vec<&str> = args[2..]
+        .iter()
+        .map(String::as_str)
+        .take_while(|arg| arg.starts_with("--"))
+        .collect();
+    if let Some(flag) = flags
+        .iter()
+        .find(|flag| !accepted.iter().any(|usage| accepts(usage, flag)))
+    {
+        eprintln!("Unknown flag for {}: {}", command, flag);
+        std::process::exit(64);
+    }
+    let Some(filename) = args.get(2 + flags.len()) else {
+        usage(&args[0]);
+    };
+    // Everything after the filename is passed on to the script.
+    let script_args = &args[3 + flags.len()..];
 
     // let (command, filename) = ("run", "test.lox");
 
@@ -21,35 +67,65 @@ fn main() {
         String::new()
     });
     let mut exit_code = 0;
-    let (tokens, errors) = lex::Tokenizer::new(&file_contents).tokenize();
+
+    if *command == "ast-json" && flags.contains(&"--load") {
+        let stmts = json::Json::parse(&file_contents).and_then(|json| json::from_json(&json));
+        match stmts {
+            Ok(stmts) => {
+                for stmt in stmts.iter() {
+                    println!("{}", stmt);
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                exit_code = 65;
+            }
+        }
+        std::process::exit(exit_code);
+    }
+
+    let mut tokenizer = lex::Tokenizer::new(&file_contents);
+    if *command == "fmt" {
+        tokenizer = tokenizer.with_comments();
+    }
+    let (tokens, errors) = tokenizer.tokenize();
     if !errors.is_empty() {
         for error in errors {
             eprintln!("{}", error);
         }
         exit_code = 65;
     }
-    if command == "tokenize" {
+    if *command == "tokenize" {
         for token in tokens.iter() {
             println!("{}", token);
         }
         std::process::exit(exit_code);
     }
 
-    if command == "parse" {
-        let (exprs, errors) = parse::Parser::from_tokens(tokens).parse_expr();
+    if *command == "parse" {
+        let (exprs, errors) = parse::Parser::from_tokens(tokens.clone()).parse_expr();
+        if errors.is_empty() {
+            for expr in exprs.iter() {
+                println!("{}", expr);
+            }
+            std::process::exit(exit_code);
+        }
+
+        // Not a bare expression, so parse it as a full program instead.
+        let (stmts, errors) = parse::Parser::from_tokens(tokens).parse();
         if !errors.is_empty() {
             for error in errors {
                 eprintln!("{}", error);
             }
             exit_code = 65;
         }
-        for expr in exprs.iter() {
-            println!("{}", expr);
+        for stmt in stmts.iter() {
+            println!("{}", stmt);
         }
         std::process::exit(exit_code);
     }
 
-    if command == "evaluate" {
+    if *command == "evaluate" {
         let (exprs, errors) = parse::Parser::from_tokens(tokens).parse_expr();
         if !errors.is_empty() {
             for error in errors {
@@ -58,11 +134,10 @@ fn main() {
             std::process::exit(65);
         }
 
-        let interpreter = interpreter::Interpreter::new();
+        let interpreter = interpreter::Interpreter::with_capabilities(&capabilities(&flags));
         for expr in exprs.iter() {
             if let Err(e) = interpreter.eval(expr) {
-                eprintln!("{}", e);
-                exit_code = 70;
+                exit_code = runtime_error(e);
                 break;
             }
         }
@@ -70,7 +145,65 @@ fn main() {
         std::process::exit(exit_code);
     }
 
-    if command == "run" {
+    if *command == "ast-json" {
+        let (stmts, errors) = parse::Parser::from_tokens(tokens).parse();
+        if !errors.is_empty() {
+            for error in errors {
+                eprintln!("{}", error);
+            }
+            std::process::exit(65);
+        }
+
+        println!("{}", json::to_json(&stmts));
+        std::process::exit(exit_code);
+    }
+
+    if *command == "fmt" {
+        if exit_code != 0 {
+            std::process::exit(exit_code);
+        }
+        let (stmts, errors) = parse::Parser::from_tokens(tokens).parse();
+        if !errors.is_empty() {
+            for error in errors {
+                eprintln!("{}", error);
+            }
+            std::process::exit(65);
+        }
+
+        if flags.contains(&"--check") {
+            if !format::Formatter::is_formatted(&file_contents, &stmts) {
+                eprintln!("{} is not formatted", filename);
+                exit_code = 1;
+            }
+        } else if let Err(e) = fs::write(filename, format::Formatter::format(&stmts)) {
+            eprintln!("Failed to write file {}: {}", filename, e);
+            exit_code = 1;
+        }
+
+        std::process::exit(exit_code);
+    }
+
+    if *command == "debug" {
+        let (stmts, errors) = parse::Parser::from_tokens(tokens).parse();
+        if !errors.is_empty() {
+            for error in errors {
+                eprintln!("{}", error);
+            }
+            std::process::exit(65);
+        }
+
+        let debugger = Rc::new(debug::Debugger::new(&file_contents));
+        let interpreter = interpreter::Interpreter::with_capabilities(&capabilities(&flags))
+            .with_args(script_args)
+            .with_tracer(debugger);
+        if let Err(e) = interpreter.interpret(&stmts) {
+            exit_code = runtime_error(e);
+        }
+
+        std::process::exit(exit_code);
+    }
+
+    if *command == "run" {
         let (exprs, errors) = parse::Parser::from_tokens(tokens).parse();
         if !errors.is_empty() {
             for error in errors {
@@ -79,12 +212,206 @@ fn main() {
             std::process::exit(65);
         }
 
-        let interpreter = interpreter::Interpreter::new();
-        if let Err(e) = interpreter.interpret(&exprs) {
+        let coverage_path = flags.iter().find_map(|flag| match *flag {
+            "--coverage" => Some("lcov.info"),
+            flag => flag.strip_prefix("--coverage="),
+        });
+
+        // Coverage needs every statement, including those that can't run.
+        let stmts = if flags.contains(&"--no-optimize") || coverage_path.is_some() {
+            exprs
+        } else {
+            optimize::Optimizer::new().optimize(exprs)
+        };
+
+        let folded = flags
+            .iter()
+            .find_map(|flag| flag.strip_prefix("--profile-folded="));
+        let profiler = (flags.contains(&"--profile") || folded.is_some())
+            .then(|| Rc::new(profile::Profiler::new()));
+
+        let coverage = coverage_path.map(|_| Rc::new(coverage::Coverage::new(filename, &stmts)));
+        // The interpreter takes a single tracer.
+        if profiler.is_some() && coverage.is_some() {
+            eprintln!("--profile and --coverage can't be used together");
+            std::process::exit(64);
+        }
+
+        let limits = limits(&flags).unwrap_or_else(|e| {
             eprintln!("{}", e);
-            exit_code = 70;
+            std::process::exit(64);
+        });
+
+        let mut interpreter = interpreter::Interpreter::with_capabilities(&capabilities(&flags))
+            .with_args(script_args)
+            .with_limits(limits);
+        if let Some(profiler) = &profiler {
+            interpreter = interpreter.with_tracer(profiler.clone());
+        }
+        if let Some(coverage) = &coverage {
+            interpreter = interpreter.with_tracer(coverage.clone());
+        }
+        if let Err(e) = interpreter.interpret(&stmts) {
+            exit_code = runtime_error(e);
+        }
+
+        if let Some(profiler) = profiler {
+            eprint!("{}", profiler.report());
+            if let Some(path) = folded {
+                if let Err(e) = fs::write(path, profiler.folded()) {
+                    eprintln!("Failed to write file {}: {}", path, e);
+                    exit_code = 1;
+                }
+            }
+        }
+
+        if let (Some(coverage), Some(path)) = (coverage, coverage_path) {
+            eprint!("{}", coverage.summary());
+            if let Err(e) = fs::write(path, coverage.lcov()) {
+                eprintln!("Failed to write file {}: {}", path, e);
+                exit_code = 1;
+            }
         }
 
         std::process::exit(exit_code);
     }
 }
+
+/// The commands taking a file, with the flags each accepts. Uppercase
+/// parts stand for a value, and bracketed parts are optional.
+const COMMANDS: &[(&str, &[&str])] = &[
+    ("tokenize", &[]),
+    ("parse", &[]),
+    ("evaluate", &["--sandbox", "--allow-CAPABILITY"]),
+    (
+        "run",
+        &[
+            "--sandbox",
+            "--allow-CAPABILITY",
+            "--max-depth=N",
+            "--max-steps=N",
+            "--timeout=MS",
+            "--no-optimize",
+            "--profile",
+            "--profile-folded=PATH",
+            "--coverage[=PATH]",
+        ],
+    ),
+    ("debug", &["--sandbox", "--allow-CAPABILITY"]),
+    ("fmt", &["--check"]),
+    ("ast-json", &["--load"]),
+];
+
+fn usage(program: &str) -> ! {
+    eprintln!("Usage: {} <command> [flags] <filename> [args...]", program);
+    eprintln!("       {} lsp", program);
+    eprintln!();
+    eprintln!("Commands:");
+    for (command, flags) in COMMANDS {
+        let line = format!("  {:<10}{}", command, flags.join(" "));
+        eprintln!("{}", line.trim_end());
+    }
+    eprintln!();
+    eprintln!("CAPABILITY is one of: all, {}", capability_names());
+    std::process::exit(64);
+}
+
+fn capability_names() -> String {
+    let names: Vec<&str> = interpreter::Capability::ALL
+        .iter()
+        .map(|capability| capability.name())
+        .collect();
+    names.join(", ")
+}
+
+/// Whether `flag` matches a flag as written in `COMMANDS`.
+fn accepts(usage: &str, flag: &str) -> bool {
+    match usage.find(|c: char| c.is_ascii_uppercase() || c == '[') {
+        None => flag == usage,
+        Some(i) if usage[i..].starts_with('[') => flag
+            .strip_prefix(&usage[..i])
+            .is_some_and(|value| value.is_empty() || value.starts_with('=')),
+        Some(i) => flag.starts_with(&usage[..i]),
+    }
+}
+
+/// Reports a runtime error and gives the exit code for it, which is the
+/// script's own for `exit(code)`.
+fn runtime_error(e: interpreter::RuntimeError) -> i32 {
+    if let interpreter::RuntimeError::Exit(code) = e {
+        let _ = io::stdout().flush();
+        return code;
+    }
+    eprintln!("{}", e);
+    70
+}
+
+/// Reads `--allow-CAPABILITY` and `--allow-all` on top of the default
+/// capabilities, or on top of none with `--sandbox`. Exits on an unknown
+/// capability.
+fn capabilities(flags: &[&str]) -> Vec<interpreter::Capability> {
+    let mut capabilities = if flags.contains(&"--sandbox") {
+        Vec::new()
+    } else {
+        interpreter::Capability::DEFAULT.to_vec()
+    };
+    for flag in flags {
+        match flag.strip_prefix("--allow-") {
+            Some("all") => capabilities = interpreter::Capability::ALL.to_vec(),
+            Some(name) => match name.parse() {
+                Ok(capability) => capabilities.push(capability),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(64);
+                }
+            },
+            None => {}
+        }
+    }
+
+    capabilities
+}
+
+/// Reads `--max-depth=N`, `--max-steps=N` and `--timeout=MS`.
+fn limits(flags: &[&str]) -> Result<interpreter::Limits, String> {
+    let mut limits = interpreter::Limits {
+        stack_size: Some(STACK_SIZE),
+        ..Default::default()
+    };
+    for flag in flags {
+        let Some((name, value)) = flag.split_once('=') else {
+            continue;
+        };
+        let parse = || {
+            value
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid value for {}: {}", name, value))
+        };
+
+        match name {
+            "--max-depth" => limits.max_depth = parse()? as usize,
+            "--max-steps" => limits.max_steps = Some(parse()?),
+            "--timeout" => limits.timeout = Some(Duration::from_millis(parse()?)),
+            _ => {}
+        }
+    }
+
+    Ok(limits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::accepts;
+
+    #[test]
+    fn matches_flags() {
+        assert!(accepts("--check", "--check"));
+        assert!(!accepts("--check", "--checked"));
+        assert!(accepts("--allow-CAPABILITY", "--allow-fs"));
+        assert!(accepts("--max-steps=N", "--max-steps=10"));
+        assert!(!accepts("--max-steps=N", "--max-step=10"));
+        assert!(accepts("--coverage[=PATH]", "--coverage"));
+        assert!(accepts("--coverage[=PATH]", "--coverage=out.info"));
+        assert!(!accepts("--coverage[=PATH]", "--coveragex"));
+    }
+}
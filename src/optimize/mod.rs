@@ -0,0 +1,259 @@
+use crate::{
+    interpreter::Interpreter,
+    lex::TokenType,
+    parse::{Expr, Statement},
+};
+
+/// Folds constant expressions and removes branches that can never run.
+pub struct Optimizer {
+    interpreter: Interpreter,
+}
+
+impl Optimizer {
+    pub fn new() -> Self {
+        Optimizer {
+            interpreter: Interpreter::new(),
+        }
+    }
+
+    pub fn optimize(&self, stmts: Vec<Statement>) -> Vec<Statement> {
+        stmts
+            .into_iter()
+            .filter_map(|stmt| self.statement(stmt))
+            .collect()
+    }
+}
+
+impl Optimizer {
+    fn statement(&self, stmt: Statement) -> Option<Statement> {
+        let stmt = match stmt {
+            Statement::Print(keyword, expr) => Statement::Print(keyword, self.expr(expr)),
+            Statement::Expression(expr) => Statement::Expression(self.expr(expr)),
+            Statement::Var(name, init) => Statement::Var(name, init.map(|init| self.expr(init))),
+            Statement::Block(stmts) => Statement::Block(self.optimize(stmts)),
+            Statement::If(keyword, cond, then_branch, else_branch) => {
+                let cond = self.expr(cond);
+                let then_branch = self.statement(*then_branch);
+                let else_branch = else_branch.and_then(|stmt| self.statement(*stmt));
+
+                match self.constant(&cond) {
+                    Some(true) => return then_branch,
+                    Some(false) => return else_branch,
+                    None => Statement::If(
+                        keyword,
+                        cond,
+                        Box::new(then_branch.unwrap_or(Statement::Block(Vec::new()))),
+                        else_branch.map(Box::new),
+                    ),
+                }
+            }
+            Statement::While(keyword, cond, body) => {
+                let cond = self.expr(cond);
+                if self.constant(&cond) == Some(false) {
+                    return None;
+                }
+
+                Statement::While(keyword, cond, self.body(*body))
+            }
+            Statement::For(keyword, init, cond, increment, body) => {
+                let init = init.and_then(|stmt| self.statement(*stmt));
+                let cond = cond.map(|cond| self.expr(cond));
+
+                // The initializer still runs once, in the loop's own scope.
+                if matches!(&cond, Some(cond) if self.constant(cond) == Some(false)) {
+                    return init.map(|init| Statement::Block(vec![init]));
+                }
+
+                Statement::For(
+                    keyword,
+                    init.map(Box::new),
+                    cond,
+                    increment.map(|increment| self.expr(increment)),
+                    self.body(*body),
+                )
+            }
+            Statement::Function(name, params, body) => {
+                Statement::Function(name, params, self.optimize(body))
+            }
+            Statement::Return(keyword, value) => {
+                Statement::Return(keyword, value.map(|value| self.expr(value)))
+            }
+            Statement::Comment(_, _) => stmt,
+        };
+
+        Some(stmt)
+    }
+
+    fn body(&self, body: Statement) -> Box<Statement> {
+        Box::new(self.statement(body).unwrap_or(Statement::Block(Vec::new())))
+    }
+
+    /// Truthiness of a condition that folded down to a literal.
+    fn constant(&self, expr: &Expr) -> Option<bool> {
+        match expr {
+            Expr::Literal(obj, _) => Some(self.interpreter.truthy(obj)),
+            _ => None,
+        }
+    }
+
+    fn expr(&self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Literal(_, _) | Expr::Variable(_) | Expr::Prefix(_, _) | Expr::Postfix(_, _) => {
+                expr
+            }
+            Expr::Grouping(inner) => self.expr(*inner),
+            Expr::Unary(op, right) => self.fold(Expr::Unary(op, Box::new(self.expr(*right)))),
+            Expr::Binary(left, op, right) => self.fold(Expr::Binary(
+                Box::new(self.expr(*left)),
+                op,
+                Box::new(self.expr(*right)),
+            )),
+            Expr::Logical(left, op, right) => {
+                let left = self.expr(*left);
+                let right = self.expr(*right);
+
+                match (self.constant(&left), op.0) {
+                    (Some(true), TokenType::Or) | (Some(false), TokenType::And) => left,
+                    (Some(_), _) => right,
+                    (None, _) => Expr::Logical(Box::new(left), op, Box::new(right)),
+                }
+            }
+            Expr::Conditional(cond, then_expr, else_expr) => {
+                let cond = self.expr(*cond);
+                let then_expr = self.expr(*then_expr);
+                let else_expr = self.expr(*else_expr);
+
+                match self.constant(&cond) {
+                    Some(true) => then_expr,
+                    Some(false) => else_expr,
+                    None => {
+                        Expr::Conditional(Box::new(cond), Box::new(then_expr), Box::new(else_expr))
+                    }
+                }
+            }
+            Expr::Assign(name, value) => Expr::Assign(name, Box::new(self.expr(*value))),
+            Expr::CompoundAssign(name, op, value) => {
+                Expr::CompoundAssign(name, op, Box::new(self.expr(*value)))
+            }
+            Expr::Call(callee, paren, args) => Expr::Call(
+                Box::new(self.expr(*callee)),
+                paren,
+                args.into_iter().map(|arg| self.expr(arg)).collect(),
+            ),
+            Expr::Interpolation(parts) => self.fold(Expr::Interpolation(
+                parts.into_iter().map(|part| self.expr(part)).collect(),
+            )),
+            Expr::Commented(expr, comments) => {
+                Expr::Commented(Box::new(self.expr(*expr)), comments)
+            }
+        }
+    }
+
+    /// Evaluates an operator applied to literals ahead of time. Operations
+    /// that would fail are kept so the error still surfaces at runtime.
+    fn fold(&self, expr: Expr) -> Expr {
+        let foldable = match &expr {
+            Expr::Unary(_, right) => is_literal(right),
+            Expr::Binary(left, _, right) => is_literal(left) && is_literal(right),
+            Expr::Interpolation(parts) => parts.iter().all(is_literal),
+            _ => false,
+        };
+
+        if foldable {
+            if let Ok(value) = self.interpreter.evaluate(&expr) {
+                return Expr::Literal(value, None);
+            }
+        }
+
+        expr
+    }
+}
+
+fn is_literal(expr: &Expr) -> bool {
+    matches!(expr, Expr::Literal(_, _))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Optimizer;
+    use crate::{interpreter::Interpreter, testing};
+
+    fn run(source: &str, optimize: bool) -> String {
+        let stmts = testing::parse(source);
+        let stmts = if optimize {
+            Optimizer::new().optimize(stmts)
+        } else {
+            stmts
+        };
+        testing::run_with(Interpreter::new(), &stmts)
+    }
+
+    fn assert_output(source: &str, expected: &str) {
+        assert_eq!(run(source, false), expected, "{}", source);
+        assert_eq!(run(source, true), expected, "{}", source);
+    }
+
+    #[test]
+    fn folds_constants() {
+        assert_output(
+            "print 1 + 2 * 3; print (1 + 2) * 3; print \"a\" + \"b\"; print !true;",
+            "7\n9\nab\nfalse\n",
+        );
+        assert_output(
+            "print 1 < 2 == true; print 7 / 2; print 1 / 0;",
+            "true\n3.5\nInfinity\n",
+        );
+        assert_output(
+            "print false or \"x\"; print nil and 1; print true ? 1 : 2;",
+            "x\nnil\n1\n",
+        );
+    }
+
+    #[test]
+    fn keeps_errors_at_runtime() {
+        assert_output(
+            "print \"before\"; print -\"a\";",
+            "before\nerror: Operand must be a number.\n",
+        );
+        assert_output("print 1; print 1 ~/ 0;", "1\nerror: Division by zero.\n");
+        assert_output(
+            "print \"a\" + 1;",
+            "error: Operands must be two numbers or two strings.\n",
+        );
+    }
+
+    #[test]
+    fn drops_dead_branches() {
+        assert_output("if (false) print \"then\"; print \"after\";", "after\n");
+        assert_output("if (true) print \"then\"; else print \"else\";", "then\n");
+        assert_output("if (nil) print \"then\"; else print \"else\";", "else\n");
+        assert_output("while (false) print \"body\"; print \"after\";", "after\n");
+    }
+
+    #[test]
+    fn keeps_for_initializer() {
+        assert_output(
+            "var calls = 0; fun f() { calls = calls + 1; return 0; }
+             for (var i = f(); false;) print \"body\";
+             print calls;",
+            "1\n",
+        );
+        // The initializer stays scoped to the loop.
+        assert_output(
+            "for (var i = 0; false;) {} print i;",
+            "error: Undefined variable 'i'.\n",
+        );
+    }
+
+    #[test]
+    fn keeps_side_effects_in_loops() {
+        assert_output(
+            "var i = 0; while (i < 3) { print i * (2 + 3); i = i + 1; }",
+            "0\n5\n10\n",
+        );
+        assert_output(
+            "fun f(n) { if (1 > 2) return 0; return n + 10 * 2; } print f(1);",
+            "21\n",
+        );
+    }
+}
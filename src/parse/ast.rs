@@ -4,27 +4,84 @@ use crate::{interpreter::Object, lex::Token};
 
 #[derive(Clone)]
 pub enum Expr {
-    Literal(Object),
+    /// The value, and for numbers and strings the lexeme it was written as.
+    /// Interpolation segments keep only the text between their delimiters.
+    Literal(Object, Option<String>),
     Unary(Token, Box<Expr>),
     Binary(Box<Expr>, Token, Box<Expr>),
     Grouping(Box<Expr>),
-    Variable(String),
-    Assign(String, Box<Expr>),
+    Variable(Token),
+    Assign(Token, Box<Expr>),
+    CompoundAssign(Token, Token, Box<Expr>),
+    Prefix(Token, Token),
+    Postfix(Token, Token),
     Logical(Box<Expr>, Token, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
     Call(Box<Expr>, Token, Vec<Expr>),
+    /// String segments alternating with the embedded expressions,
+    /// always starting and ending with a segment.
+    Interpolation(Vec<Expr>),
+    /// A call argument followed by the comments written after it, only
+    /// produced when the tokenizer keeps comments.
+    Commented(Box<Expr>, Vec<Token>),
+}
+
+impl Expr {
+    /// The line of the expression's first token, if it has any.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Expr::Literal(_, _) => None,
+            Expr::Unary(op, _) | Expr::Prefix(op, _) => Some(op.3),
+            Expr::Binary(left, op, _) | Expr::Logical(left, op, _) => left.line().or(Some(op.3)),
+            Expr::Grouping(expr) => expr.line(),
+            Expr::Variable(name)
+            | Expr::Assign(name, _)
+            | Expr::CompoundAssign(name, _, _)
+            | Expr::Postfix(name, _) => Some(name.3),
+            Expr::Conditional(cond, then_expr, else_expr) => cond
+                .line()
+                .or_else(|| then_expr.line())
+                .or_else(|| else_expr.line()),
+            Expr::Call(callee, paren, _) => callee.line().or(Some(paren.3)),
+            Expr::Interpolation(parts) => parts.iter().find_map(Expr::line),
+            Expr::Commented(expr, _) => expr.line(),
+        }
+    }
 }
 
 impl Display for Expr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let output = match self {
-            Expr::Literal(obj) => obj.to_string(),
+            Expr::Literal(obj, _) => obj.to_string(),
             Expr::Unary(token, right) => format!("({} {})", token.1, right),
             Expr::Binary(left, token, right) => format!("({} {} {})", token.1, left, right),
             Expr::Grouping(expr) => format!("(group {})", expr),
-            Expr::Variable(name) => name.to_string(),
-            Expr::Assign(name, expr) => format!("(assign {} {})", name, expr),
+            Expr::Variable(name) => name.1.to_string(),
+            Expr::Assign(name, expr) => format!("(assign {} {})", name.1, expr),
+            Expr::CompoundAssign(name, op, expr) => format!("({} {} {})", op.1, name.1, expr),
+            Expr::Prefix(op, name) => format!("(pre{} {})", op.1, name.1),
+            Expr::Postfix(name, op) => format!("(post{} {})", op.1, name.1),
             Expr::Logical(left, token, right) => format!("({} {} {})", token.1, left, right),
-            Expr::Call(_, _, _) => todo!(),
+            Expr::Conditional(cond, then_expr, else_expr) => {
+                format!("(?: {} {} {})", cond, then_expr, else_expr)
+            }
+            Expr::Call(callee, _, args) => {
+                let mut output = format!("(call {}", callee);
+                for arg in args {
+                    output.push_str(&format!(" {}", arg));
+                }
+                output.push(')');
+                output
+            }
+            Expr::Interpolation(parts) => {
+                let mut output = "(interpolation".to_string();
+                for part in parts {
+                    output.push_str(&format!(" {}", part));
+                }
+                output.push(')');
+                output
+            }
+            Expr::Commented(expr, _) => expr.to_string(),
         };
 
         write!(f, "{}", output)
@@ -33,23 +90,86 @@ impl Display for Expr {
 
 #[derive(Clone)]
 pub enum Statement {
-    Print(Expr),
+    Print(Token, Expr),
     Expression(Expr),
-    Var(String, Option<Expr>),
+    Var(Token, Option<Expr>),
     Block(Vec<Statement>),
-    If(Expr, Box<Statement>, Option<Box<Statement>>),
-    While(Expr, Box<Statement>),
-    Function(String, Vec<Token>, Vec<Statement>),
+    If(Token, Expr, Box<Statement>, Option<Box<Statement>>),
+    While(Token, Expr, Box<Statement>),
+    For(
+        Token,
+        Option<Box<Statement>>,
+        Option<Expr>,
+        Option<Expr>,
+        Box<Statement>,
+    ),
+    Function(Token, Vec<Token>, Vec<Statement>),
     Return(Token, Option<Expr>),
+    /// A source comment, only produced when the tokenizer keeps comments.
+    /// The flag is set when the comment trails code on the same line.
+    Comment(Token, bool),
+}
+
+impl Statement {
+    /// The line the statement starts on. Only expression statements made
+    /// of bare literals, and blocks of them, have none.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Statement::Print(keyword, _)
+            | Statement::If(keyword, _, _, _)
+            | Statement::While(keyword, _, _)
+            | Statement::For(keyword, _, _, _, _)
+            | Statement::Return(keyword, _) => Some(keyword.3),
+            Statement::Var(name, _) | Statement::Function(name, _, _) => Some(name.3),
+            Statement::Expression(expr) => expr.line(),
+            Statement::Block(stmts) => stmts.iter().find_map(Statement::line),
+            Statement::Comment(token, _) => Some(token.3),
+        }
+    }
 }
 
 impl Display for Statement {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let output = match self {
+            Statement::Print(_, expr) => format!("(print {})", expr),
             Statement::Expression(expr) => format!("{}", expr),
-            _ => "Not implemented".to_string(),
+            Statement::Var(name, Some(init)) => format!("(var {} {})", name.1, init),
+            Statement::Var(name, None) => format!("(var {})", name.1),
+            Statement::Block(stmts) => format!("(block{})", join(stmts)),
+            Statement::If(_, cond, then_branch, Some(else_branch)) => {
+                format!("(if {} {} {})", cond, then_branch, else_branch)
+            }
+            Statement::If(_, cond, then_branch, None) => format!("(if {} {})", cond, then_branch),
+            Statement::While(_, cond, body) => format!("(while {} {})", cond, body),
+            Statement::For(_, init, cond, increment, body) => format!(
+                "(for {} {} {} {})",
+                or_empty(init),
+                or_empty(cond),
+                or_empty(increment),
+                body
+            ),
+            Statement::Function(name, params, body) => {
+                let params: Vec<&str> = params.iter().map(|param| param.1.as_str()).collect();
+                format!("(fun {} ({}){})", name.1, params.join(" "), join(body))
+            }
+            Statement::Return(keyword, Some(value)) => format!("({} {})", keyword.1, value),
+            Statement::Return(keyword, None) => format!("({})", keyword.1),
+            Statement::Comment(token, _) => format!("(comment {})", token.1),
         };
 
         write!(f, "{}", output)
     }
 }
+
+/// Space-prefixed concatenation of statements, for variable-length forms.
+fn join(stmts: &[Statement]) -> String {
+    stmts.iter().map(|stmt| format!(" {}", stmt)).collect()
+}
+
+/// Missing optional parts of a form print as `()`.
+fn or_empty<T: Display>(part: &Option<T>) -> String {
+    match part {
+        Some(part) => part.to_string(),
+        None => "()".to_string(),
+    }
+}
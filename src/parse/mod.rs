@@ -11,11 +11,24 @@ mod ast;
 pub enum ParserError {
     #[error("[line {0}] Error at '{1}': Expect expression.")]
     UnexpectedToken(usize, String),
+    #[error("[line {0}] Error at '{1}': Invalid assignment target.")]
+    InvalidAssignment(usize, String),
+}
+
+impl ParserError {
+    pub fn line(&self) -> usize {
+        match self {
+            ParserError::UnexpectedToken(line, _) | ParserError::InvalidAssignment(line, _) => {
+                *line
+            }
+        }
+    }
 }
 
 pub struct Parser {
     tokens: Vec<Token>,
     pos: usize,
+    comments: Vec<Token>,
 }
 
 impl Parser {
@@ -23,9 +36,16 @@ impl Parser {
         let mut stmts = Vec::new();
         let mut errors = Vec::new();
 
-        while !self.is_at_end() {
+        loop {
+            stmts.extend(self.comments());
+            if self.is_at_end() {
+                break;
+            }
             match self.declaration() {
-                Ok(expr) => stmts.push(expr),
+                Ok(stmt) => {
+                    stmts.extend(self.skipped());
+                    stmts.push(stmt);
+                }
                 Err(e) => errors.push(e),
             }
         }
@@ -82,7 +102,7 @@ impl Parser {
             ));
         };
 
-        Ok(Statement::Function(name.1, params, body))
+        Ok(Statement::Function(name, params, body))
     }
 
     fn parameters(&mut self) -> Result<Vec<Token>, ParserError> {
@@ -110,7 +130,7 @@ impl Parser {
 
         self.expected(TokenType::Semicolon)?;
 
-        Ok(Statement::Var(name.1, expr))
+        Ok(Statement::Var(name, expr))
     }
 
     fn statement(&mut self) -> Result<Statement, ParserError> {
@@ -138,22 +158,19 @@ impl Parser {
     }
 
     fn for_statment(&mut self) -> Result<Statement, ParserError> {
-        self.expected(TokenType::For)?;
+        let keyword = self.expected(TokenType::For)?.clone();
         self.expected(TokenType::LeftParen)?;
         let init = match self.peek().0 {
             TokenType::Semicolon => {
-                self.pos += 1;
+                self.next();
                 None
             }
-            TokenType::Var => Some(self.var_decl()?),
-            _ => Some(self.expression_statement()?),
+            TokenType::Var => Some(Box::new(self.var_decl()?)),
+            _ => Some(Box::new(self.expression_statement()?)),
         };
         let condition = match self.peek().0 {
-            TokenType::Semicolon => {
-                self.pos += 1;
-                Expr::Literal(Object::Boolean(true))
-            }
-            _ => self.expression()?,
+            TokenType::Semicolon => None,
+            _ => Some(self.expression()?),
         };
         self.expected(TokenType::Semicolon)?;
         let increment = match self.peek().0 {
@@ -162,32 +179,23 @@ impl Parser {
         };
         self.expected(TokenType::RightParen)?;
 
-        let mut body = self.statement()?;
-        if let Some(increment) = increment {
-            body = Statement::Block(vec![body, Statement::Expression(increment)]);
-        }
-
-        body = Statement::While(condition, Box::new(body));
-
-        if let Some(init) = init {
-            body = Statement::Block(vec![init, body]);
-        }
+        let body = Box::new(self.statement()?);
 
-        Ok(body)
+        Ok(Statement::For(keyword, init, condition, increment, body))
     }
 
     fn while_statment(&mut self) -> Result<Statement, ParserError> {
-        self.expected(TokenType::While)?;
+        let keyword = self.expected(TokenType::While)?.clone();
         self.expected(TokenType::LeftParen)?;
         let condition = self.expression()?;
         self.expected(TokenType::RightParen)?;
         let body = Box::new(self.statement()?);
 
-        Ok(Statement::While(condition, body))
+        Ok(Statement::While(keyword, condition, body))
     }
 
     fn if_statment(&mut self) -> Result<Statement, ParserError> {
-        self.expected(TokenType::If)?;
+        let keyword = self.expected(TokenType::If)?.clone();
         self.expected(TokenType::LeftParen)?;
         let condition = self.expression()?;
         self.expected(TokenType::RightParen)?;
@@ -199,27 +207,37 @@ impl Parser {
             None
         };
 
-        Ok(Statement::If(condition, then_branch, else_branch))
+        Ok(Statement::If(keyword, condition, then_branch, else_branch))
     }
 
     fn block_statement(&mut self) -> Result<Statement, ParserError> {
         self.expected(TokenType::LeftBrace)?;
+        // Comments before the brace belong to the enclosing statement.
+        let mut outer = std::mem::take(&mut self.comments);
         let mut stmts = Vec::new();
 
-        while self.peek().0 != TokenType::RightBrace && !self.is_at_end() {
-            stmts.push(self.declaration()?);
+        loop {
+            stmts.extend(self.comments());
+            if self.peek().0 == TokenType::RightBrace || self.is_at_end() {
+                break;
+            }
+            let stmt = self.declaration()?;
+            stmts.extend(self.skipped());
+            stmts.push(stmt);
         }
 
         self.expected(TokenType::RightBrace)?;
+        outer.append(&mut self.comments);
+        self.comments = outer;
 
         Ok(Statement::Block(stmts))
     }
 
     fn print_statement(&mut self) -> Result<Statement, ParserError> {
-        self.expected(TokenType::Print)?;
+        let keyword = self.expected(TokenType::Print)?.clone();
         let expr = self.expression()?;
         self.expected(TokenType::Semicolon)?;
-        Ok(Statement::Print(expr))
+        Ok(Statement::Print(keyword, expr))
     }
 
     fn expression_statement(&mut self) -> Result<Statement, ParserError> {
@@ -231,7 +249,11 @@ impl Parser {
 
 impl Parser {
     pub fn from_tokens(tokens: Vec<Token>) -> Self {
-        Parser { tokens, pos: 0 }
+        Parser {
+            tokens,
+            pos: 0,
+            comments: Vec::new(),
+        }
     }
 
     fn expression(&mut self) -> Result<Expr, ParserError> {
@@ -239,23 +261,44 @@ impl Parser {
     }
 
     fn assignment(&mut self) -> Result<Expr, ParserError> {
-        let expr = self.logic_or()?;
+        let expr = self.conditional()?;
 
-        if self.peek().0 == TokenType::Equal {
-            self.next();
-            let value = self.assignment()?;
-            match expr {
-                Expr::Variable(name) => Ok(Expr::Assign(name, Box::new(value))),
-                _ => Err(ParserError::UnexpectedToken(
-                    self.peek().3,
-                    self.peek().1.clone(),
-                )),
+        if let TokenType::Equal
+        | TokenType::PlusEqual
+        | TokenType::MinusEqual
+        | TokenType::StarEqual
+        | TokenType::SlashEqual = self.peek().0
+        {
+            let operator = self.next().clone();
+            let value = Box::new(self.assignment()?);
+            match (expr, operator.0) {
+                (Expr::Variable(name), TokenType::Equal) => Ok(Expr::Assign(name, value)),
+                (Expr::Variable(name), _) => Ok(Expr::CompoundAssign(name, operator, value)),
+                _ => Err(ParserError::InvalidAssignment(operator.3, operator.1)),
             }
         } else {
             Ok(expr)
         }
     }
 
+    fn conditional(&mut self) -> Result<Expr, ParserError> {
+        let cond = self.logic_or()?;
+
+        if self.peek().0 == TokenType::Question {
+            self.next();
+            let then_expr = self.expression()?;
+            self.expected(TokenType::Colon)?;
+            let else_expr = self.conditional()?;
+            return Ok(Expr::Conditional(
+                Box::new(cond),
+                Box::new(then_expr),
+                Box::new(else_expr),
+            ));
+        }
+
+        Ok(cond)
+    }
+
     fn logic_or(&mut self) -> Result<Expr, ParserError> {
         let mut expr = self.logic_and()?;
         while self.peek().0 == TokenType::Or {
@@ -294,9 +337,7 @@ impl Parser {
         while let TokenType::Greater
         | TokenType::GreaterEqual
         | TokenType::Less
-        | TokenType::LessEqual
-        | TokenType::EqualEqual
-        | TokenType::BangEqual = self.peek().0
+        | TokenType::LessEqual = self.peek().0
         {
             let operator = self.next().clone();
             let right = self.term()?;
@@ -319,9 +360,59 @@ impl Parser {
     }
 
     fn factor(&mut self) -> Result<Expr, ParserError> {
+        let mut expr = self.bitwise_or()?;
+
+        while let TokenType::Star | TokenType::Slash | TokenType::TildeSlash | TokenType::Percent =
+            self.peek().0
+        {
+            let operator = self.next().clone();
+            let right = self.bitwise_or()?;
+            expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
+    fn bitwise_or(&mut self) -> Result<Expr, ParserError> {
+        let mut expr = self.bitwise_xor()?;
+
+        while let TokenType::Pipe = self.peek().0 {
+            let operator = self.next().clone();
+            let right = self.bitwise_xor()?;
+            expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
+    fn bitwise_xor(&mut self) -> Result<Expr, ParserError> {
+        let mut expr = self.bitwise_and()?;
+
+        while let TokenType::Caret = self.peek().0 {
+            let operator = self.next().clone();
+            let right = self.bitwise_and()?;
+            expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
+    fn bitwise_and(&mut self) -> Result<Expr, ParserError> {
+        let mut expr = self.shift()?;
+
+        while let TokenType::Ampersand = self.peek().0 {
+            let operator = self.next().clone();
+            let right = self.shift()?;
+            expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
+    fn shift(&mut self) -> Result<Expr, ParserError> {
         let mut expr = self.unary()?;
 
-        while let TokenType::Star | TokenType::Slash = self.peek().0 {
+        while let TokenType::LessLess | TokenType::GreaterGreater = self.peek().0 {
             let operator = self.next().clone();
             let right = self.unary()?;
             expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
@@ -331,15 +422,37 @@ impl Parser {
     }
 
     fn unary(&mut self) -> Result<Expr, ParserError> {
-        if let TokenType::Minus | TokenType::Bang = self.peek().0 {
+        if let TokenType::PlusPlus | TokenType::MinusMinus = self.peek().0 {
+            let operator = self.next().clone();
+            return match self.unary()? {
+                Expr::Variable(name) => Ok(Expr::Prefix(operator, name)),
+                _ => Err(ParserError::InvalidAssignment(operator.3, operator.1)),
+            };
+        }
+
+        if let TokenType::Minus | TokenType::Bang | TokenType::Tilde = self.peek().0 {
             let operator = self.next().clone();
             let right = self.unary()?;
             Ok(Expr::Unary(operator, Box::new(right)))
         } else {
-            self.call()
+            self.power()
         }
     }
 
+    /// `**` binds tighter than prefix operators on its left, so `-2 ** 2`
+    /// is `-(2 ** 2)`, and is right-associative through `unary`.
+    fn power(&mut self) -> Result<Expr, ParserError> {
+        let expr = self.call()?;
+
+        if let TokenType::StarStar = self.peek().0 {
+            let operator = self.next().clone();
+            let right = self.unary()?;
+            return Ok(Expr::Binary(Box::new(expr), operator, Box::new(right)));
+        }
+
+        Ok(expr)
+    }
+
     fn call(&mut self) -> Result<Expr, ParserError> {
         let mut expr = self.primary()?;
 
@@ -354,40 +467,94 @@ impl Parser {
             expr = Expr::Call(Box::new(expr), paren, args);
         }
 
+        if let TokenType::PlusPlus | TokenType::MinusMinus = self.peek().0 {
+            let operator = self.next().clone();
+            return match expr {
+                Expr::Variable(name) => Ok(Expr::Postfix(name, operator)),
+                _ => Err(ParserError::InvalidAssignment(operator.3, operator.1)),
+            };
+        }
+
         Ok(expr)
     }
 
     fn arguments(&mut self) -> Result<Vec<Expr>, ParserError> {
         let mut args = Vec::new();
 
-        args.push(self.expression()?);
-        while let TokenType::Comma = self.peek().0 {
-            self.next();
-            args.push(self.expression()?);
-        }
+        loop {
+            let start = self.comments.len();
+            let arg = self.expression()?;
+            let more = self.peek().0 == TokenType::Comma;
+            if more {
+                self.next();
+            }
+
+            // Every comment up to the next argument stays with this one.
+            while self.tokens[self.pos].0 == TokenType::Comment {
+                self.comments.push(self.tokens[self.pos].clone());
+                self.pos += 1;
+            }
+            let comments: Vec<Token> = self.comments.drain(start..).collect();
+            args.push(if comments.is_empty() {
+                arg
+            } else {
+                Expr::Commented(Box::new(arg), comments)
+            });
 
-        Ok(args)
+            if !more {
+                return Ok(args);
+            }
+        }
     }
 
     fn primary(&mut self) -> Result<Expr, ParserError> {
         let peeked = self.next();
         let expr = match peeked.0 {
-            TokenType::Number | TokenType::String => Expr::Literal(peeked.2.clone().unwrap()),
-            TokenType::True => Expr::Literal(Object::Boolean(true)),
-            TokenType::False => Expr::Literal(Object::Boolean(false)),
-            TokenType::Nil => Expr::Literal(Object::Nil),
+            TokenType::Number => Expr::Literal(peeked.2.clone().unwrap(), Some(peeked.1.clone())),
+            TokenType::String => Expr::Literal(peeked.2.clone().unwrap(), Some(peeked.1.clone())),
+            TokenType::True => Expr::Literal(Object::Boolean(true), None),
+            TokenType::False => Expr::Literal(Object::Boolean(false), None),
+            TokenType::Nil => Expr::Literal(Object::Nil, None),
             TokenType::LeftParen => {
                 let expr = self.expression()?;
                 self.expected(TokenType::RightParen)?;
                 Expr::Grouping(Box::new(expr))
             }
-            TokenType::Identifier => Expr::Variable(peeked.1.to_string()),
+            TokenType::Identifier => Expr::Variable(peeked.clone()),
+            TokenType::Interpolation => {
+                let head = segment(peeked);
+                self.interpolation(head)?
+            }
             _ => return Err(ParserError::UnexpectedToken(peeked.3, peeked.1.to_string())),
         };
 
         Ok(expr)
     }
 
+    fn interpolation(&mut self, head: Expr) -> Result<Expr, ParserError> {
+        let mut parts = vec![head];
+
+        loop {
+            // A segment straight after `${` means the braces were empty.
+            if self.peek().1.starts_with('}') {
+                return Err(ParserError::UnexpectedToken(
+                    self.peek().3,
+                    self.peek().1.clone(),
+                ));
+            }
+            parts.push(self.expression()?);
+            let segment = self.next().clone();
+            match segment.0 {
+                TokenType::Interpolation => parts.push(self::segment(&segment)),
+                TokenType::String => {
+                    parts.push(self::segment(&segment));
+                    return Ok(Expr::Interpolation(parts));
+                }
+                _ => return Err(ParserError::UnexpectedToken(segment.3, segment.1)),
+            }
+        }
+    }
+
     /// Expects the next token to be of the given type.
     /// If not, rasies an error.
     fn expected(&mut self, token_type: TokenType) -> Result<&Token, ParserError> {
@@ -402,15 +569,174 @@ impl Parser {
     }
 
     fn next(&mut self) -> &Token {
-        self.pos += 1;
-        &self.tokens[self.pos - 1]
+        while self.tokens[self.pos].0 == TokenType::Comment {
+            self.comments.push(self.tokens[self.pos].clone());
+            self.pos += 1;
+        }
+        if self.tokens[self.pos].0 != TokenType::Eof {
+            self.pos += 1;
+            return &self.tokens[self.pos - 1];
+        }
+        &self.tokens[self.pos]
     }
 
     fn peek(&self) -> &Token {
-        &self.tokens[self.pos]
+        self.tokens[self.pos..]
+            .iter()
+            .find(|token| token.0 != TokenType::Comment)
+            .unwrap_or(&self.tokens[self.tokens.len() - 1])
+    }
+
+    /// Drains the comments skipped inside the statement just parsed, which
+    /// are written on their own lines ahead of it.
+    fn skipped(&mut self) -> Vec<Statement> {
+        self.comments
+            .drain(..)
+            .map(|token| Statement::Comment(token, false))
+            .collect()
+    }
+
+    /// Collects the comments sitting between statements.
+    fn comments(&mut self) -> Vec<Statement> {
+        let mut stmts = Vec::new();
+        while self.tokens[self.pos].0 == TokenType::Comment {
+            let token = self.tokens[self.pos].clone();
+            let trailing = self.pos > 0 && self.tokens[self.pos - 1].3 == token.3;
+            stmts.push(Statement::Comment(token, trailing));
+            self.pos += 1;
+        }
+
+        stmts
     }
 
     fn is_at_end(&self) -> bool {
         self.peek().0 == TokenType::Eof
     }
 }
+
+/// A string segment of an interpolation, keeping the text between its
+/// delimiters (`"` or `}` before it, `${` or `"` after it) as the lexeme.
+fn segment(token: &Token) -> Expr {
+    let close = if token.0 == TokenType::Interpolation {
+        2
+    } else {
+        1
+    };
+    let lexeme = &token.1[1..token.1.len() - close];
+    Expr::Literal(token.2.clone().unwrap(), Some(lexeme.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Parser;
+    use crate::testing::{parse_expr, tokenize};
+
+    /// Binary operators from loosest to tightest binding.
+    const LEVELS: &[&[&str]] = &[
+        &["or"],
+        &["and"],
+        &["==", "!="],
+        &[">", ">=", "<", "<="],
+        &["-", "+"],
+        &["*", "/", "~/", "%"],
+        &["|"],
+        &["^"],
+        &["&"],
+        &["<<", ">>"],
+    ];
+
+    fn parse(source: &str) -> String {
+        parse_expr(source)
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn equality_binds_looser_than_comparison() {
+        assert_eq!(parse("a < b == c"), "(== (< a b) c)");
+        assert_eq!(parse("a == b < c"), "(== a (< b c))");
+        assert_eq!(parse("a != b >= c != d"), "(!= (!= a (>= b c)) d)");
+        assert_eq!(parse("a == b == c"), "(== (== a b) c)");
+        assert_eq!(parse("a < b < c"), "(< (< a b) c)");
+    }
+
+    #[test]
+    fn every_operator_pair() {
+        let operators = LEVELS
+            .iter()
+            .enumerate()
+            .flat_map(|(level, ops)| ops.iter().map(move |op| (level, *op)));
+
+        for (left_level, left) in operators.clone() {
+            for (right_level, right) in operators.clone() {
+                // Operators on the same level associate to the left.
+                let expected = if left_level >= right_level {
+                    format!("({} ({} a b) c)", right, left)
+                } else {
+                    format!("({} a ({} b c))", left, right)
+                };
+                assert_eq!(parse(&format!("a {} b {} c", left, right)), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn grouping_overrides_precedence() {
+        assert_eq!(parse("(a == b) < c"), "(< (group (== a b)) c)");
+        assert_eq!(parse("a * (b + c)"), "(* a (group (+ b c)))");
+        assert_eq!(parse("-a * b"), "(* (- a) b)");
+        assert_eq!(parse("!a == b"), "(== (! a) b)");
+    }
+
+    /// The errors from parsing `source` as a program.
+    fn errors(source: &str) -> Vec<String> {
+        let (_, errors) = Parser::from_tokens(tokenize(source)).parse();
+        errors.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn interpolations_need_an_expression() {
+        assert_eq!(
+            errors(r#"print "a${}b";"#),
+            [r#"[line 1] Error at '}b"': Expect expression."#]
+        );
+
+        assert_eq!(
+            parse(r#""a${b}c${"d${e}"}""#),
+            "(interpolation a b c (interpolation d e ) )"
+        );
+    }
+
+    #[test]
+    fn compound_assignment_needs_a_variable() {
+        assert_eq!(parse("a += b * c"), "(+= a (* b c))");
+        assert_eq!(parse("a = b -= 1"), "(assign a (-= b 1.0))");
+        assert_eq!(parse("a++ + ++b"), "(+ (post++ a) (pre++ b))");
+        assert_eq!(
+            errors("1 += 2;")[0],
+            "[line 1] Error at '+=': Invalid assignment target."
+        );
+        assert_eq!(
+            errors("(a)--;")[0],
+            "[line 1] Error at '--': Invalid assignment target."
+        );
+        assert_eq!(
+            errors("++1;")[0],
+            "[line 1] Error at '++': Invalid assignment target."
+        );
+    }
+
+    #[test]
+    fn conditional_is_right_associative() {
+        assert_eq!(parse("a ? b : c ? d : e"), "(?: a b (?: c d e))");
+        assert_eq!(parse("a ? b ? c : d : e"), "(?: a (?: b c d) e)");
+        assert_eq!(parse("a or b ? c : d"), "(?: (or a b) c d)");
+        assert_eq!(parse("a = b ? c : d"), "(assign a (?: b c d))");
+        assert_eq!(
+            errors("a ? b;")[0],
+            "[line 1] Error at ';': Expect expression."
+        );
+    }
+}
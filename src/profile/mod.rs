@@ -0,0 +1,357 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    interpreter::{Interpreter, RuntimeError, Tracer},
+    parse::Statement,
+};
+
+/// Name of the pseudo-function for code outside any function.
+const SCRIPT: &str = "<script>";
+
+/// Records call counts and time spent per function and per source line.
+///
+/// Self time is measured between consecutive interpreter events and
+/// charged to the innermost running statement and function. Total time
+/// spans a function call or statement, counted once when it recurses.
+pub struct Profiler {
+    state: RefCell<State>,
+}
+
+#[derive(Default)]
+struct Stats {
+    count: u64,
+    self_time: Duration,
+    total_time: Duration,
+    /// How many calls of it are running, so recursion counts total time
+    /// once.
+    running: usize,
+}
+
+struct Call {
+    key: (String, usize),
+    start: Instant,
+    /// Index into `State::stacks`.
+    stack: usize,
+}
+
+/// A distinct call stack, stored as the stack it was called from plus the
+/// function called, so deep recursion takes linear memory.
+struct Stack {
+    /// Index into `State::stacks`, or `None` for the script itself.
+    parent: Option<usize>,
+    name: String,
+    self_time: Duration,
+}
+
+struct State {
+    /// Keyed by function name and declaration line.
+    functions: HashMap<(String, usize), Stats>,
+    lines: HashMap<usize, Stats>,
+    /// Every call stack seen. The first is the script itself, and callers
+    /// always come before their callees.
+    stacks: Vec<Stack>,
+    /// Stack indexes by the stack they were called from and the function.
+    stack_ids: HashMap<(usize, String), usize>,
+    calls: Vec<Call>,
+    statements: Vec<(usize, Instant)>,
+    start: Instant,
+    /// When time was last charged to anything.
+    last: Instant,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Profiler {
+            state: RefCell::new(State {
+                functions: HashMap::new(),
+                lines: HashMap::new(),
+                stacks: vec![Stack {
+                    parent: None,
+                    name: SCRIPT.to_string(),
+                    self_time: Duration::ZERO,
+                }],
+                stack_ids: HashMap::new(),
+                calls: Vec::new(),
+                statements: Vec::new(),
+                start: now,
+                last: now,
+            }),
+        }
+    }
+
+    /// A table of functions by total time and lines by self time.
+    pub fn report(&self) -> String {
+        let mut state = self.state.borrow_mut();
+        state.charge();
+        let elapsed = state.start.elapsed();
+
+        let mut functions: Vec<_> = state.functions.iter().collect();
+        functions.sort_by(|(a_key, a), (b_key, b)| {
+            b.total_time.cmp(&a.total_time).then(a_key.cmp(b_key))
+        });
+        let script = Stats {
+            count: 1,
+            self_time: state.stacks[0].self_time,
+            total_time: elapsed,
+            running: 0,
+        };
+
+        let mut report = String::new();
+        let _ = writeln!(report, "Functions by total time:");
+        let _ = writeln!(report, "{}", header("function"));
+        let _ = writeln!(report, "{}", row(&script, SCRIPT));
+        for ((name, line), stats) in functions {
+            let _ = writeln!(
+                report,
+                "{}",
+                row(stats, &format!("{} (line {})", name, line))
+            );
+        }
+
+        let mut lines: Vec<_> = state.lines.iter().collect();
+        lines.sort_by(|(a_line, a), (b_line, b)| {
+            b.self_time.cmp(&a.self_time).then(a_line.cmp(b_line))
+        });
+
+        let _ = writeln!(report, "\nLines by self time:");
+        let _ = writeln!(report, "{}", header("line"));
+        for (line, stats) in lines {
+            let _ = writeln!(report, "{}", row(stats, &line.to_string()));
+        }
+
+        report
+    }
+
+    /// One `stack;of;functions microseconds` line per distinct call stack.
+    pub fn folded(&self) -> String {
+        let mut state = self.state.borrow_mut();
+        state.charge();
+
+        let mut paths: Vec<String> = Vec::with_capacity(state.stacks.len());
+        for stack in state.stacks.iter() {
+            let path = match stack.parent {
+                Some(parent) => format!("{};{}", paths[parent], stack.name),
+                None => stack.name.clone(),
+            };
+            paths.push(path);
+        }
+
+        let mut lines: Vec<_> = paths
+            .into_iter()
+            .zip(state.stacks.iter())
+            .map(|(path, stack)| format!("{} {}\n", path, stack.self_time.as_micros()))
+            .collect();
+        lines.sort();
+        lines.concat()
+    }
+}
+
+impl State {
+    /// Charges the time since the last event to whatever is running now.
+    fn charge(&mut self) {
+        let now = Instant::now();
+        let elapsed = now - self.last;
+        self.last = now;
+
+        if let Some((line, _)) = self.statements.last() {
+            self.lines.entry(*line).or_default().self_time += elapsed;
+        }
+        if let Some(call) = self.calls.last() {
+            self.functions.get_mut(&call.key).unwrap().self_time += elapsed;
+        }
+        let stack = self.stack();
+        self.stacks[stack].self_time += elapsed;
+    }
+
+    /// The index of the running call stack.
+    fn stack(&self) -> usize {
+        self.calls.last().map_or(0, |call| call.stack)
+    }
+}
+
+impl Tracer for Profiler {
+    fn statement(&self, _interpreter: &Interpreter, stmt: &Statement) -> Result<(), RuntimeError> {
+        let Some(line) = stmt.line() else {
+            return Ok(());
+        };
+
+        let mut state = self.state.borrow_mut();
+        state.charge();
+        let now = state.last;
+        let stats = state.lines.entry(line).or_default();
+        stats.count += 1;
+        stats.running += 1;
+        state.statements.push((line, now));
+        Ok(())
+    }
+
+    fn statement_end(&self, stmt: &Statement) {
+        if stmt.line().is_none() {
+            return;
+        }
+
+        let mut state = self.state.borrow_mut();
+        state.charge();
+        let Some((line, start)) = state.statements.pop() else {
+            return;
+        };
+        let elapsed = state.last - start;
+        let stats = state.lines.entry(line).or_default();
+        stats.running -= 1;
+        // A line already running further out will count this time itself.
+        if stats.running == 0 {
+            stats.total_time += elapsed;
+        }
+    }
+
+    fn call(&self, name: &str, line: usize) {
+        let mut state = self.state.borrow_mut();
+        state.charge();
+        let now = state.last;
+        let key = (name.to_string(), line);
+        let stats = state.functions.entry(key.clone()).or_default();
+        stats.count += 1;
+        stats.running += 1;
+
+        let caller = state.stack();
+        let State {
+            stacks, stack_ids, ..
+        } = &mut *state;
+        let stack = *stack_ids
+            .entry((caller, name.to_string()))
+            .or_insert_with(|| {
+                stacks.push(Stack {
+                    parent: Some(caller),
+                    name: name.to_string(),
+                    self_time: Duration::ZERO,
+                });
+                stacks.len() - 1
+            });
+        state.calls.push(Call {
+            key,
+            start: now,
+            stack,
+        });
+    }
+
+    fn ret(&self) {
+        let mut state = self.state.borrow_mut();
+        state.charge();
+        let Some(call) = state.calls.pop() else {
+            return;
+        };
+        let elapsed = state.last - call.start;
+        let stats = state.functions.get_mut(&call.key).unwrap();
+        stats.running -= 1;
+        if stats.running == 0 {
+            stats.total_time += elapsed;
+        }
+    }
+}
+
+fn header(name: &str) -> String {
+    format!(
+        "{:>10} {:>12} {:>12}  {}",
+        "count", "total ms", "self ms", name
+    )
+}
+
+fn row(stats: &Stats, name: &str) -> String {
+    format!(
+        "{:>10} {:>12.3} {:>12.3}  {}",
+        stats.count,
+        stats.total_time.as_secs_f64() * 1000.0,
+        stats.self_time.as_secs_f64() * 1000.0,
+        name
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::Profiler;
+    use crate::{
+        interpreter::Interpreter,
+        testing::{parse, run_with},
+    };
+
+    const SOURCE: &str = "\
+fun down(n) {
+  if (n > 0) down(n - 1);
+}
+fun twice() {
+  down(2);
+  down(0);
+}
+twice();
+down(1);
+";
+
+    fn profile() -> Rc<Profiler> {
+        let profiler = Rc::new(Profiler::new());
+        let interpreter = Interpreter::new().with_tracer(profiler.clone());
+        assert_eq!(run_with(interpreter, &parse(SOURCE)), "");
+        profiler
+    }
+
+    #[test]
+    fn folds_call_stacks() {
+        let folded = profile().folded();
+        let stacks: Vec<&str> = folded
+            .lines()
+            .map(|line| line.rsplit_once(' ').unwrap().0)
+            .collect();
+        assert_eq!(
+            stacks,
+            [
+                "<script>",
+                "<script>;down",
+                "<script>;down;down",
+                "<script>;twice",
+                "<script>;twice;down",
+                "<script>;twice;down;down",
+                "<script>;twice;down;down;down",
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_calls_and_lines() {
+        let report = profile().report();
+        let count = |name: &str| {
+            report
+                .lines()
+                .find(|line| line.ends_with(name))
+                .and_then(|line| line.split_whitespace().next())
+                .unwrap_or_else(|| panic!("no row for {}", name))
+                .to_string()
+        };
+        assert_eq!(count("<script>"), "1");
+        assert_eq!(count("twice (line 4)"), "1");
+        // down(2) makes three calls, down(0) one and down(1) two.
+        assert_eq!(count("down (line 1)"), "6");
+        // Each call runs the `if`, and three of them the call inside it.
+        assert_eq!(count("  2"), "9");
+        assert_eq!(count("  8"), "1");
+    }
+
+    #[test]
+    fn deep_recursion_stays_linear() {
+        let profiler = Rc::new(Profiler::new());
+        let source = "fun f(n) { if (n > 0) f(n - 1); } f(100);";
+        let interpreter = Interpreter::new().with_tracer(profiler.clone());
+        assert_eq!(run_with(interpreter, &parse(source)), "");
+
+        let state = profiler.state.borrow();
+        assert_eq!(state.stacks.len(), 102);
+        let names: usize = state.stacks.iter().map(|stack| stack.name.len()).sum();
+        assert!(names < 102 * 8);
+    }
+}
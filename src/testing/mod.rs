@@ -0,0 +1,61 @@
+//! Helpers shared by the unit tests.
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    interpreter::{Interpreter, Object, RuntimeError},
+    lex::{Token, Tokenizer},
+    parse::{Expr, Parser, Statement},
+};
+
+/// The tokens of source that must lex without errors.
+pub fn tokenize(source: &str) -> Vec<Token> {
+    let (tokens, errors) = Tokenizer::new(source).tokenize();
+    assert!(errors.is_empty(), "{}", source);
+    tokens
+}
+
+/// The statements of a program that must parse without errors.
+pub fn parse(source: &str) -> Vec<Statement> {
+    parse_tokens(source, tokenize(source))
+}
+
+/// Like `parse`, keeping comments as statements.
+pub fn parse_with_comments(source: &str) -> Vec<Statement> {
+    let (tokens, errors) = Tokenizer::new(source).with_comments().tokenize();
+    assert!(errors.is_empty(), "{}", source);
+    parse_tokens(source, tokens)
+}
+
+fn parse_tokens(source: &str, tokens: Vec<Token>) -> Vec<Statement> {
+    let (stmts, errors) = Parser::from_tokens(tokens).parse();
+    assert!(errors.is_empty(), "{}", source);
+    stmts
+}
+
+pub fn parse_expr(source: &str) -> Vec<Expr> {
+    let (exprs, errors) = Parser::from_tokens(tokenize(source)).parse_expr();
+    assert!(errors.is_empty(), "{}", source);
+    exprs
+}
+
+/// The value of a single expression.
+pub fn evaluate(source: &str) -> Result<Object, RuntimeError> {
+    Interpreter::new().evaluate(&parse_expr(source)[0])
+}
+
+/// What a program prints, followed by the runtime error it stops with.
+pub fn run(source: &str) -> String {
+    run_with(Interpreter::new(), &parse(source))
+}
+
+pub fn run_with(interpreter: Interpreter, stmts: &[Statement]) -> String {
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let result = interpreter.with_output(output.clone()).interpret(stmts);
+
+    let mut output = String::from_utf8(output.take()).unwrap();
+    if let Err(e) = result {
+        output += &format!("error: {}\n", e);
+    }
+    output
+}